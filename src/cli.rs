@@ -0,0 +1,217 @@
+use std::{io::Write, path::PathBuf};
+
+use bilive_danmaku::event::Event;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    error::Error,
+    page::{liveroom::{self, RoomConnection, RoomUpdate}, login::LoginPageService, PageService, PageServiceHandle},
+    service::{profile::{self, Profile}, record, webapi::WebApiService},
+};
+
+/// 终端里的 bilibili 直播弹幕客户端
+#[derive(Debug, Parser)]
+#[command(name = "biliterm", version, about)]
+pub struct Cli {
+    /// 启动时打开的直播间, 可重复
+    #[arg(long = "room", value_name = "ROOMID")]
+    pub rooms: Vec<u64>,
+    /// 启动时把这些直播间合并到一个时间线页面, 用逗号分隔
+    #[arg(long, value_name = "ROOMID,...", value_delimiter = ',')]
+    pub timeline: Vec<u64>,
+    /// 启动时打开的录制文件回放, 可重复
+    #[arg(long = "replay", value_name = "FILE")]
+    pub replays: Vec<PathBuf>,
+    /// 不恢复上次退出时打开的标签页
+    #[arg(long)]
+    pub no_restore: bool,
+    /// 把打开的直播间事件录制到 `$XDG_DATA_HOME/biliterm/records`
+    #[arg(long)]
+    pub record: bool,
+    /// 使用的账号档案, 缺省为上次使用的档案
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// 配置文件路径, 缺省为 `$XDG_CONFIG_HOME/biliterm/config.toml`
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 在标准输出中显示二维码并扫码登录
+    Login,
+    /// 不进入 TUI, 把直播间事件逐行输出到标准输出
+    Tail(TailArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct TailArgs {
+    /// 要收听的直播间
+    #[arg(required = true, value_name = "ROOMID")]
+    pub rooms: Vec<u64>,
+    /// 每个事件输出一行 JSON
+    #[arg(long)]
+    pub json: bool,
+    /// 只输出这些类型的事件
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub only: Vec<EventKind>,
+    /// 不输出这些类型的事件
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub skip: Vec<EventKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    Danmaku,
+    Gift,
+    Guard,
+    Superchat,
+    Entry,
+    Watched,
+    Popularity,
+    Other,
+}
+
+impl EventKind {
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::Danmaku { .. } => EventKind::Danmaku,
+            Event::Gift { .. } | Event::BlindboxGift { .. } => EventKind::Gift,
+            Event::GuardBuy { .. } => EventKind::Guard,
+            Event::SuperChat { .. } => EventKind::Superchat,
+            Event::EnterRoom { .. } | Event::GuardEnterRoom { .. } | Event::Follow { .. } => EventKind::Entry,
+            Event::WatchedUpdate { .. } => EventKind::Watched,
+            Event::PopularityUpdate { .. } => EventKind::Popularity,
+            _ => EventKind::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Danmaku => "danmaku",
+            EventKind::Gift => "gift",
+            EventKind::Guard => "guard",
+            EventKind::Superchat => "superchat",
+            EventKind::Entry => "entry",
+            EventKind::Watched => "watched",
+            EventKind::Popularity => "popularity",
+            EventKind::Other => "other",
+        }
+    }
+}
+
+impl TailArgs {
+    fn accepts(&self, kind: EventKind) -> bool {
+        (self.only.is_empty() || self.only.contains(&kind)) && !self.skip.contains(&kind)
+    }
+}
+
+impl Cli {
+    pub fn profile(&self) -> Result<Profile, Error> {
+        match &self.profile {
+            Some(name) if profile::is_valid_name(name) => Ok(Profile::new(name.as_str())),
+            Some(name) => Err(Error::InvalidProfileName(name.clone())),
+            None => Ok(Profile::last_active()),
+        }
+    }
+}
+
+/// 不进入 TUI, 直接在标准输出中走一遍登录流程
+pub async fn login(webapi_service: &WebApiService) -> Result<(), Error> {
+    println!("登录账号: {}", webapi_service.profile.name);
+    let PageServiceHandle { mut watcher, .. } = LoginPageService::new(&webapi_service.bilibili).run();
+    let mut last = watcher.borrow().clone();
+    while watcher.changed().await.is_ok() {
+        let page = watcher.borrow().clone();
+        if page.qrcode != last.qrcode {
+            if let Some(qrcode) = &page.qrcode {
+                println!("{qrcode}");
+            }
+        }
+        if page.lint != last.lint {
+            println!("{}", page.lint);
+        }
+        last = page;
+    }
+    // 失败或中途放弃的登录不切换默认账号
+    if !last.success {
+        return Err(Error::LoginFail(last.lint));
+    }
+    webapi_service.profile.set_active().map_err(Error::Io)
+}
+
+/// 标准输入读到 EOF 时完成. 在单独的线程中阻塞读取, 进程退出时不用等它
+fn stdin_eof() -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        std::io::copy(&mut std::io::stdin().lock(), &mut std::io::sink()).unwrap_or_default();
+        tx.send(()).unwrap_or_default();
+    });
+    rx
+}
+
+/// 把若干直播间的事件输出到标准输出, 直到 Ctrl-C, 标准输入结束, 所有连接关闭, 或者下游关闭管道.
+/// 各直播间分别连接, 连不上的在标准错误中报告, 不影响其它直播间
+pub async fn tail(args: TailArgs) -> Result<(), Error> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for &roomid in args.rooms.iter() {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut connection = match liveroom::connect(roomid).await {
+                Ok(service) => RoomConnection::new(roomid, service),
+                Err(_) => {
+                    eprintln!("{}", Error::ConnectLiveRoomFail(roomid));
+                    return;
+                }
+            };
+            // 断线会自动重连, 放弃后结束
+            while let Some(update) = connection.next().await {
+                match update {
+                    RoomUpdate::Event(e) => {
+                        if tx.send((roomid, e)).is_err() {
+                            break;
+                        }
+                    },
+                    RoomUpdate::Lagged(n) => eprintln!("直播间 {roomid} 跳过了 {n} 条消息"),
+                    RoomUpdate::Status(status) => eprintln!("直播间 {roomid} {status}"),
+                }
+            }
+        });
+    }
+    drop(tx);
+    let mut eof = stdin_eof();
+    let mut stdout = std::io::stdout();
+    loop {
+        let (roomid, event) = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = &mut eof => break,
+            next = rx.recv() => match next {
+                Some(next) => next,
+                None => break,
+            },
+        };
+        let kind = EventKind::of(&event);
+        if !args.accepts(kind) {
+            continue;
+        }
+        let line = if args.json {
+            serde_json::json!({
+                "ts": record::now_millis(),
+                "roomid": roomid,
+                "kind": kind.name(),
+                "event": event,
+            }).to_string()
+        } else {
+            format!("{roomid} [{}] {}", kind.name(), liveroom::event_text(&event))
+        };
+        match writeln!(stdout, "{line}").and_then(|_|stdout.flush()) {
+            Ok(()) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    Ok(())
+}
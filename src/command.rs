@@ -0,0 +1,184 @@
+use crate::config::Binding;
+
+/// 命令参数的种类, 决定补全的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    None,
+    Rooms,
+    Text,
+    Theme,
+    Layout,
+}
+
+/// 命令面板中的一条命令. 有对应按键的命令不带参数时与按键效果相同
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub arg: Arg,
+    pub binding: Option<Binding>,
+}
+
+pub static COMMANDS: [CommandSpec; 15] = [
+    CommandSpec { name: "open", usage: "open <房间号>...", arg: Arg::Rooms, binding: Some(Binding::OpenRoom) },
+    CommandSpec { name: "close", usage: "close", arg: Arg::None, binding: Some(Binding::ClosePage) },
+    CommandSpec { name: "send", usage: "send <弹幕>", arg: Arg::Text, binding: Some(Binding::SendDanmaku) },
+    CommandSpec { name: "filter", usage: "filter add|rm|list [global] <规则>", arg: Arg::Text, binding: Some(Binding::EditFilter) },
+    CommandSpec { name: "timeline", usage: "timeline <房间号>...", arg: Arg::Rooms, binding: Some(Binding::OpenTimeline) },
+    CommandSpec { name: "replay", usage: "replay <文件>", arg: Arg::Text, binding: Some(Binding::OpenReplay) },
+    CommandSpec { name: "export", usage: "export", arg: Arg::None, binding: None },
+    CommandSpec { name: "login", usage: "login", arg: Arg::None, binding: Some(Binding::Login) },
+    CommandSpec { name: "profile", usage: "profile <账号>", arg: Arg::Text, binding: Some(Binding::SwitchProfile) },
+    CommandSpec { name: "theme", usage: "theme default|dark|light|mono", arg: Arg::Theme, binding: None },
+    CommandSpec { name: "layout", usage: "layout [single|horizontal|vertical|grid]", arg: Arg::Layout, binding: Some(Binding::CycleLayout) },
+    CommandSpec { name: "log", usage: "log", arg: Arg::None, binding: Some(Binding::ToggleLog) },
+    CommandSpec { name: "retry", usage: "retry", arg: Arg::None, binding: Some(Binding::Retry) },
+    CommandSpec { name: "help", usage: "help", arg: Arg::None, binding: Some(Binding::Help) },
+    CommandSpec { name: "quit", usage: "quit", arg: Arg::None, binding: Some(Binding::Quit) },
+];
+
+pub const THEMES: [&str; 4] = ["default", "dark", "light", "mono"];
+pub const LAYOUTS: [&str; 4] = ["single", "horizontal", "vertical", "grid"];
+
+pub enum Resolved {
+    Command(&'static CommandSpec),
+    Binding(Binding),
+}
+
+/// 按名字找命令; 其它按键操作也可以用按键表中的名字当作命令
+pub fn resolve(name: &str) -> Option<Resolved> {
+    COMMANDS.iter().find(|c| c.name == name).map(Resolved::Command)
+        .or_else(|| Binding::from_name(name).map(Resolved::Binding))
+}
+
+/// 按键对应的命令行: 有命令的用命令名(如 `open_room` 为 `open`), 其它用按键表中的名字
+pub fn binding_command(binding: Binding) -> &'static str {
+    COMMANDS.iter().find(|c| c.binding == Some(binding)).map_or(binding.name(), |c| c.name)
+}
+
+/// 补全候选: `value` 是填入输入行的内容, `label` 是显示的说明
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub value: String,
+    pub label: String,
+}
+
+/// 模糊匹配: `pattern` 的字符按顺序出现在 `text` 中即可; 分数越小越好, 前缀匹配最优
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<usize> {
+    if pattern.is_empty() {
+        return Some(text.chars().count());
+    }
+    if text.starts_with(pattern) {
+        return Some(0);
+    }
+    let mut chars = text.char_indices();
+    let mut first = None;
+    let mut last = 0;
+    for p in pattern.chars() {
+        let (i, _) = chars.find(|(_, c)| c.eq_ignore_ascii_case(&p))?;
+        first.get_or_insert(i);
+        last = i;
+    }
+    // 匹配越紧凑越好
+    Some(1 + last - first.unwrap_or(0))
+}
+
+fn rank(pattern: &str, candidates: impl Iterator<Item = Candidate>) -> Vec<Candidate> {
+    let mut scored: Vec<(usize, Candidate)> = candidates.filter_map(|c| {
+        let score = fuzzy_score(pattern, &c.value).into_iter().chain(fuzzy_score(pattern, &c.label)).min()?;
+        Some((score, c))
+    }).collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, c)| c).collect()
+}
+
+/// 命令行最后一个词的补全候选. `rooms` 是已知的 (房间号, 名字)
+pub fn complete(line: &str, rooms: &[(u64, String)]) -> Vec<Candidate> {
+    let Some((name, rest)) = line.split_once(' ') else {
+        let commands = COMMANDS.iter().map(|c| Candidate { value: c.name.to_string(), label: c.usage.to_string() });
+        let bindings = Binding::ALL.into_iter().filter(|b| COMMANDS.iter().all(|c| c.binding != Some(*b))).map(|b| Candidate { value: b.name().to_string(), label: b.description().to_string() });
+        return rank(line, commands.chain(bindings));
+    };
+    let word = rest.rsplit(' ').next().unwrap_or_default();
+    let arg = match resolve(name) {
+        Some(Resolved::Command(spec)) => spec.arg,
+        _ => Arg::None,
+    };
+    let words = |list: &'static [&'static str]| list.iter().map(|w| Candidate { value: w.to_string(), label: String::new() });
+    match arg {
+        Arg::Rooms => rank(word, rooms.iter().map(|(roomid, name)| Candidate { value: roomid.to_string(), label: name.clone() })),
+        Arg::Theme => rank(word, words(&THEMES)),
+        Arg::Layout => rank(word, words(&LAYOUTS)),
+        Arg::None | Arg::Text => Vec::new(),
+    }
+}
+
+/// 用候选替换命令行的最后一个词
+pub fn apply_candidate(line: &str, candidate: &Candidate) -> String {
+    match line.rsplit_once(' ') {
+        Some((head, _)) => format!("{head} {} ", candidate.value),
+        None => format!("{} ", candidate.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(candidates: Vec<Candidate>) -> Vec<String> {
+        candidates.into_iter().map(|c| c.value).collect()
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_prefix_and_compact_matches() {
+        assert_eq!(fuzzy_score("", "open"), Some(4));
+        assert_eq!(fuzzy_score("op", "open"), Some(0));
+        assert_eq!(fuzzy_score("tl", "timeline"), Some(5));
+        assert_eq!(fuzzy_score("TL", "timeline"), Some(5));
+        assert_eq!(fuzzy_score("tn", "timeline"), Some(7));
+        assert_eq!(fuzzy_score("x", "timeline"), None);
+        assert_eq!(fuzzy_score("lt", "timeline"), None);
+    }
+
+    #[test]
+    fn complete_commands_and_bindings() {
+        let candidates = values(complete("ti", &[]));
+        assert_eq!(candidates[0], "timeline");
+        // 有命令的按键不重复出现
+        assert!(!candidates.iter().any(|c| c == "open_timeline"));
+        assert!(values(complete("scroll_up", &[])).contains(&"scroll_up".to_string()));
+    }
+
+    #[test]
+    fn complete_arguments() {
+        let rooms = [(1, "甲".to_string()), (22, "乙".to_string())];
+        assert_eq!(values(complete("open 2", &rooms)), ["22"]);
+        assert_eq!(values(complete("timeline 1 乙", &rooms)), ["22"]);
+        assert_eq!(values(complete("layout g", &[])), ["grid", "single"]);
+        assert_eq!(values(complete("theme mo", &[])), ["mono"]);
+        assert!(complete("send h", &rooms).is_empty());
+        assert!(complete("nope x", &rooms).is_empty());
+    }
+
+    #[test]
+    fn apply_candidate_replaces_last_word() {
+        let candidate = Candidate { value: "22".into(), label: String::new() };
+        assert_eq!(apply_candidate("open 1 2", &candidate), "open 1 22 ");
+        assert_eq!(apply_candidate("op", &Candidate { value: "open".into(), label: String::new() }), "open ");
+    }
+
+    #[test]
+    fn bindings_map_to_command_lines() {
+        assert_eq!(binding_command(Binding::OpenRoom), "open");
+        assert_eq!(binding_command(Binding::CycleLayout), "layout");
+        assert_eq!(binding_command(Binding::ScrollUp), "scroll_up");
+        // 每个按键的命令行都能找回原来的操作
+        for binding in Binding::ALL {
+            let resolved = match resolve(binding_command(binding)) {
+                Some(Resolved::Command(spec)) => spec.binding,
+                Some(Resolved::Binding(binding)) => Some(binding),
+                None => None,
+            };
+            assert_eq!(resolved, Some(binding));
+        }
+    }
+}
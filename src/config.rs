@@ -0,0 +1,678 @@
+use std::{fmt::Display, path::{Path, PathBuf}};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use tui::style::{Color, Modifier, Style};
+
+use crate::{service::{profile, danmaku}, style::Theme, page::liveroom::DEFAULT_HISTORY_SIZE};
+
+pub const DEFAULT_MAX_FPS: u64 = 30;
+
+/// 可绑定按键的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Quit,
+    ClosePage,
+    OpenRoom,
+    Login,
+    SwitchProfile,
+    NextPage,
+    PrevPage,
+    SendDanmaku,
+    ToggleEntries,
+    ScrollUp,
+    ScrollDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    Follow,
+    OpenReplay,
+    TogglePause,
+    SpeedUp,
+    SpeedDown,
+    ToggleLog,
+    Retry,
+    ResendFailed,
+    SelectPrev,
+    SelectNext,
+    ClearSelection,
+    Reply,
+    CopyMessage,
+    EditFilter,
+    HideUser,
+    Silence,
+    Blacklist,
+    ListMuted,
+    Unmute,
+    UserInfo,
+    CycleLayout,
+    GrowPane,
+    ShrinkPane,
+    OpenTimeline,
+    Help,
+    CommandPalette,
+}
+
+impl Binding {
+    pub const ALL: [Binding; 39] = [
+        Binding::Quit,
+        Binding::ClosePage,
+        Binding::OpenRoom,
+        Binding::Login,
+        Binding::SwitchProfile,
+        Binding::NextPage,
+        Binding::PrevPage,
+        Binding::SendDanmaku,
+        Binding::ToggleEntries,
+        Binding::ScrollUp,
+        Binding::ScrollDown,
+        Binding::ScrollPageUp,
+        Binding::ScrollPageDown,
+        Binding::Follow,
+        Binding::OpenReplay,
+        Binding::TogglePause,
+        Binding::SpeedUp,
+        Binding::SpeedDown,
+        Binding::ToggleLog,
+        Binding::Retry,
+        Binding::ResendFailed,
+        Binding::SelectPrev,
+        Binding::SelectNext,
+        Binding::ClearSelection,
+        Binding::Reply,
+        Binding::CopyMessage,
+        Binding::EditFilter,
+        Binding::HideUser,
+        Binding::Silence,
+        Binding::Blacklist,
+        Binding::ListMuted,
+        Binding::Unmute,
+        Binding::UserInfo,
+        Binding::CycleLayout,
+        Binding::GrowPane,
+        Binding::ShrinkPane,
+        Binding::OpenTimeline,
+        Binding::Help,
+        Binding::CommandPalette,
+    ];
+
+    /// 配置文件 `[keys]` 中使用的名字
+    pub fn name(&self) -> &'static str {
+        match self {
+            Binding::Quit => "quit",
+            Binding::ClosePage => "close_page",
+            Binding::OpenRoom => "open_room",
+            Binding::Login => "login",
+            Binding::SwitchProfile => "switch_profile",
+            Binding::NextPage => "next_page",
+            Binding::PrevPage => "prev_page",
+            Binding::SendDanmaku => "send_danmaku",
+            Binding::ToggleEntries => "toggle_entries",
+            Binding::ScrollUp => "scroll_up",
+            Binding::ScrollDown => "scroll_down",
+            Binding::ScrollPageUp => "scroll_page_up",
+            Binding::ScrollPageDown => "scroll_page_down",
+            Binding::Follow => "follow",
+            Binding::OpenReplay => "open_replay",
+            Binding::TogglePause => "toggle_pause",
+            Binding::SpeedUp => "speed_up",
+            Binding::SpeedDown => "speed_down",
+            Binding::ToggleLog => "toggle_log",
+            Binding::Retry => "retry",
+            Binding::ResendFailed => "resend_failed",
+            Binding::SelectPrev => "select_prev",
+            Binding::SelectNext => "select_next",
+            Binding::ClearSelection => "clear_selection",
+            Binding::Reply => "reply",
+            Binding::CopyMessage => "copy_message",
+            Binding::EditFilter => "edit_filter",
+            Binding::HideUser => "hide_user",
+            Binding::Silence => "silence",
+            Binding::Blacklist => "blacklist",
+            Binding::ListMuted => "list_muted",
+            Binding::Unmute => "unmute",
+            Binding::UserInfo => "user_info",
+            Binding::CycleLayout => "cycle_layout",
+            Binding::GrowPane => "grow_pane",
+            Binding::ShrinkPane => "shrink_pane",
+            Binding::OpenTimeline => "open_timeline",
+            Binding::Help => "help",
+            Binding::CommandPalette => "command_palette",
+        }
+    }
+
+    /// 帮助界面和提示行中的说明
+    pub fn description(&self) -> &'static str {
+        match self {
+            Binding::Quit => "退出",
+            Binding::ClosePage => "关闭标签页",
+            Binding::OpenRoom => "打开直播间",
+            Binding::Login => "扫码登录",
+            Binding::SwitchProfile => "切换账号",
+            Binding::NextPage => "下一个标签页",
+            Binding::PrevPage => "上一个标签页",
+            Binding::SendDanmaku => "发弹幕",
+            Binding::ToggleEntries => "展开/收起进场和关注",
+            Binding::ScrollUp => "向上滚动",
+            Binding::ScrollDown => "向下滚动",
+            Binding::ScrollPageUp => "向上翻页",
+            Binding::ScrollPageDown => "向下翻页",
+            Binding::Follow => "回到最新",
+            Binding::OpenReplay => "打开回放",
+            Binding::TogglePause => "暂停/继续回放",
+            Binding::SpeedUp => "回放加速",
+            Binding::SpeedDown => "回放减速",
+            Binding::ToggleLog => "显示/隐藏日志",
+            Binding::Retry => "重试连接",
+            Binding::ResendFailed => "重发失败的弹幕",
+            Binding::SelectPrev => "选中上一条",
+            Binding::SelectNext => "选中下一条",
+            Binding::ClearSelection => "取消选中/关闭弹窗",
+            Binding::Reply => "回复",
+            Binding::CopyMessage => "复制",
+            Binding::EditFilter => "过滤规则",
+            Binding::HideUser => "隐藏该用户的消息",
+            Binding::Silence => "禁言",
+            Binding::Blacklist => "拉黑(永久禁言)",
+            Binding::ListMuted => "禁言列表",
+            Binding::Unmute => "解除禁言",
+            Binding::UserInfo => "用户信息",
+            Binding::CycleLayout => "切换布局",
+            Binding::GrowPane => "放大窗格",
+            Binding::ShrinkPane => "缩小窗格",
+            Binding::OpenTimeline => "合并时间线",
+            Binding::Help => "帮助",
+            Binding::CommandPalette => "命令面板",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    /// 只在直播间或回放页面(且未编辑输入行)时生效的操作
+    pub fn is_live_room(&self) -> bool {
+        matches!(self,
+            Binding::SendDanmaku | Binding::ToggleEntries |
+            Binding::ScrollUp | Binding::ScrollDown |
+            Binding::ScrollPageUp | Binding::ScrollPageDown | Binding::Follow |
+            Binding::TogglePause | Binding::SpeedUp | Binding::SpeedDown |
+            Binding::ResendFailed | Binding::SelectPrev | Binding::SelectNext |
+            Binding::ClearSelection | Binding::Reply | Binding::CopyMessage |
+            Binding::EditFilter | Binding::HideUser | Binding::Silence |
+            Binding::Blacklist | Binding::ListMuted | Binding::Unmute |
+            Binding::UserInfo
+        )
+    }
+
+    fn default_chords(&self) -> &'static [&'static str] {
+        match self {
+            Binding::Quit => &["ctrl-c"],
+            Binding::ClosePage => &["ctrl-w"],
+            Binding::OpenRoom => &["ctrl-r"],
+            Binding::Login => &["ctrl-l"],
+            Binding::SwitchProfile => &["ctrl-p"],
+            Binding::NextPage => &["ctrl-,", "ctrl-tab", "pagedown"],
+            Binding::PrevPage => &["ctrl-.", "pageup"],
+            Binding::SendDanmaku => &["t"],
+            Binding::ToggleEntries => &["e"],
+            Binding::ScrollUp => &["k"],
+            Binding::ScrollDown => &["j"],
+            Binding::ScrollPageUp => &["pageup"],
+            Binding::ScrollPageDown => &["pagedown"],
+            Binding::Follow => &["end"],
+            Binding::OpenReplay => &["ctrl-o"],
+            Binding::TogglePause => &["space"],
+            Binding::SpeedUp => &["+", "="],
+            Binding::SpeedDown => &["-"],
+            Binding::ToggleLog => &["ctrl-g"],
+            Binding::Retry => &["r"],
+            Binding::ResendFailed => &["R"],
+            Binding::SelectPrev => &["up"],
+            Binding::SelectNext => &["down"],
+            Binding::ClearSelection => &["esc"],
+            Binding::Reply => &["@"],
+            Binding::CopyMessage => &["y"],
+            Binding::EditFilter => &["f"],
+            Binding::HideUser => &["x"],
+            Binding::Silence => &["m"],
+            Binding::Blacklist => &["b"],
+            Binding::ListMuted => &["M"],
+            Binding::Unmute => &["U"],
+            Binding::UserInfo => &["i"],
+            Binding::CycleLayout => &["ctrl-t"],
+            Binding::GrowPane => &["ctrl-right"],
+            Binding::ShrinkPane => &["ctrl-left"],
+            Binding::OpenTimeline => &["ctrl-n"],
+            Binding::Help => &["?", "f1"],
+            Binding::CommandPalette => &[":"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// 解析 `ctrl-r`, `alt-shift-x`, `pageup`, `f1` 这样的按键描述
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut rest = s.trim();
+        let mut modifiers = KeyModifiers::NONE;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            if lower.len() > 5 && lower.starts_with("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = &rest[5..];
+            } else if lower.len() > 4 && lower.starts_with("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = &rest[4..];
+            } else if lower.len() > 6 && lower.starts_with("shift-") {
+                modifiers |= KeyModifiers::SHIFT;
+                rest = &rest[6..];
+            } else {
+                break;
+            }
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                f if f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+                _ => return None,
+            },
+        };
+        Some(Self { code, modifiers })
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        match (self.code, key.code) {
+            // 字符键上的 Shift 已经体现在字符本身, 例如 `+` 在多数键盘上要按 Shift,
+            // 所以没有写 Shift 的按键忽略它
+            (KeyCode::Char(a), KeyCode::Char(b)) if !self.modifiers.contains(KeyModifiers::SHIFT) => {
+                a == b && self.modifiers == key.modifiers - KeyModifiers::SHIFT
+            },
+            // 写了 Shift 的字母键, 终端报告的是大写字母
+            (KeyCode::Char(a), KeyCode::Char(b)) => a.eq_ignore_ascii_case(&b) && self.modifiers == key.modifiers,
+            (a, b) => a == b && self.modifiers == key.modifiers,
+        }
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("Alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            f.write_str("Shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// 按键表, 同一个按键可以绑定多个操作, 按顺序取第一个在当前上下文生效的
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    pub bindings: Vec<(KeyChord, Binding)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut bindings = Vec::new();
+        // 直播间的按键优先, 例如 PageUp 在直播间内是滚动, 在其他页面是切换标签页
+        for binding in Binding::ALL.into_iter().filter(Binding::is_live_room).chain(Binding::ALL.into_iter().filter(|b| !b.is_live_room())) {
+            for chord in binding.default_chords() {
+                bindings.push((KeyChord::parse(chord).expect("default key chord"), binding));
+            }
+        }
+        Self { bindings }
+    }
+}
+
+impl KeyMap {
+    pub fn matching<'a>(&'a self, key: &'a KeyEvent) -> impl Iterator<Item = Binding> + 'a {
+        self.bindings.iter().filter(move |(chord, _)| chord.matches(key)).map(|(_, b)| *b)
+    }
+
+    /// 某个操作的全部按键
+    pub fn chords(&self, binding: Binding) -> impl Iterator<Item = &KeyChord> + '_ {
+        self.bindings.iter().filter(move |(_, b)| *b == binding).map(|(chord, _)| chord)
+    }
+
+    /// 用新的按键替换某个操作原有的全部按键
+    pub fn rebind(&mut self, binding: Binding, chords: Vec<KeyChord>) {
+        let at = self.bindings.iter().position(|(_, b)| *b == binding).unwrap_or(self.bindings.len());
+        self.bindings.retain(|(_, b)| *b != binding);
+        let at = at.min(self.bindings.len());
+        for (i, chord) in chords.into_iter().enumerate() {
+            self.bindings.insert(at + i, (chord, binding));
+        }
+    }
+}
+
+/// 启动时从 `config.toml` 读取的设置
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keys: KeyMap,
+    pub theme: Theme,
+    pub max_fps: u64,
+    pub history_size: usize,
+    /// 两条弹幕之间的最短间隔
+    pub danmaku_cooldown_ms: u64,
+    /// 弹幕长度上限(字符数), 取不到直播间给出的上限时使用
+    pub danmaku_max_length: usize,
+    /// 除当前账号的昵称(登录后自动获取)外, 额外需要高亮的名字
+    pub mention_names: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keys: KeyMap::default(),
+            theme: Theme::default(),
+            max_fps: DEFAULT_MAX_FPS,
+            history_size: DEFAULT_HISTORY_SIZE,
+            danmaku_cooldown_ms: danmaku::DEFAULT_COOLDOWN_MS,
+            danmaku_max_length: danmaku::DEFAULT_MAX_LENGTH,
+            mention_names: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        profile::config_dir().join("config.toml")
+    }
+
+    /// 读取配置文件. 文件不存在时使用默认配置;
+    /// 格式错误的条目会被跳过, 并作为警告返回, 交给消息行显示
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(e) => (Self::default(), vec![format!("无法读取配置文件 {}: {e}", path.display())]),
+        }
+    }
+
+    /// 解析配置文件的内容, 规则同 [`Config::load`]
+    pub fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut warnings = Vec::new();
+        let root = match toml::from_str::<toml::Value>(text) {
+            Ok(toml::Value::Table(root)) => root,
+            Ok(_) => return (config, warnings),
+            Err(e) => {
+                warnings.push(format!("配置文件格式错误: {e}"));
+                return (config, warnings);
+            }
+        };
+        for (key, value) in root.iter() {
+            match key.as_str() {
+                "max_fps" => match value.as_integer() {
+                    Some(fps) if (1..=1000).contains(&fps) => config.max_fps = fps as u64,
+                    _ => warnings.push("配置项 max_fps 应为 1 到 1000 之间的整数".into()),
+                },
+                // 旧版本按固定间隔重绘, 现在只在页面变化时重绘, 保留这一项以免旧配置报错
+                "tick_rate_ms" => warnings.push("配置项 tick_rate_ms 已废弃, 将被忽略, 刷新频率上限请用 max_fps".into()),
+                "history_size" => match value.as_integer() {
+                    Some(n) if n > 0 => config.history_size = n as usize,
+                    _ => warnings.push("配置项 history_size 应为正整数".into()),
+                },
+                "danmaku_cooldown_ms" => match value.as_integer() {
+                    Some(ms) if ms >= 0 => config.danmaku_cooldown_ms = ms as u64,
+                    _ => warnings.push("配置项 danmaku_cooldown_ms 应为非负整数".into()),
+                },
+                "danmaku_max_length" => match value.as_integer() {
+                    Some(n) if n > 0 => config.danmaku_max_length = n as usize,
+                    _ => warnings.push("配置项 danmaku_max_length 应为正整数".into()),
+                },
+                "mention_names" => match value.as_array().and_then(|names| names.iter().map(|n| n.as_str().map(String::from)).collect::<Option<Vec<_>>>()) {
+                    Some(names) => config.mention_names = names,
+                    None => warnings.push("配置项 mention_names 应为字符串数组".into()),
+                },
+                "keys" => config.load_keys(value, &mut warnings),
+                "colors" => config.load_colors(value, &mut warnings),
+                other => warnings.push(format!("未知的配置项 {other}")),
+            }
+        }
+        (config, warnings)
+    }
+
+    fn load_keys(&mut self, value: &toml::Value, warnings: &mut Vec<String>) {
+        let Some(table) = value.as_table() else {
+            warnings.push("配置项 keys 应为表".into());
+            return;
+        };
+        for (name, chords) in table.iter() {
+            let Some(binding) = Binding::from_name(name) else {
+                warnings.push(format!("未知的按键操作 keys.{name}"));
+                continue;
+            };
+            let chords: Vec<&toml::Value> = match chords {
+                toml::Value::Array(chords) => chords.iter().collect(),
+                chord => vec![chord],
+            };
+            let mut parsed = Vec::with_capacity(chords.len());
+            for chord in chords {
+                match chord.as_str().and_then(KeyChord::parse) {
+                    Some(chord) => parsed.push(chord),
+                    None => warnings.push(format!("keys.{name}: 无法识别的按键 {chord}")),
+                }
+            }
+            if !parsed.is_empty() {
+                self.keys.rebind(binding, parsed);
+            }
+        }
+    }
+
+    fn load_colors(&mut self, value: &toml::Value, warnings: &mut Vec<String>) {
+        let Some(table) = value.as_table() else {
+            warnings.push("配置项 colors 应为表".into());
+            return;
+        };
+        for (name, spec) in table.iter() {
+            let Some(style) = self.theme.style_mut(name) else {
+                warnings.push(format!("未知的样式 colors.{name}"));
+                continue;
+            };
+            match parse_style(spec) {
+                Ok(parsed) => *style = parsed,
+                Err(e) => warnings.push(format!("colors.{name}: {e}")),
+            }
+        }
+    }
+}
+
+/// `{ fg = "white", bg = "blue", bold = true }`, 未写出的字段不设置
+fn parse_style(spec: &toml::Value) -> Result<Style, String> {
+    let table = spec.as_table().ok_or_else(|| "应为表".to_string())?;
+    let mut style = Style::default();
+    for (key, value) in table.iter() {
+        match key.as_str() {
+            "fg" | "bg" => {
+                let color = value.as_str().and_then(parse_color).ok_or_else(|| format!("无法识别的颜色 {value}"))?;
+                style = if key == "fg" { style.fg(color) } else { style.bg(color) };
+            }
+            "bold" | "italic" | "underlined" | "dim" | "reversed" => {
+                let on = value.as_bool().ok_or_else(|| format!("{key} 应为布尔值"))?;
+                let modifier = match key.as_str() {
+                    "bold" => Modifier::BOLD,
+                    "italic" => Modifier::ITALIC,
+                    "underlined" => Modifier::UNDERLINED,
+                    "dim" => Modifier::DIM,
+                    _ => Modifier::REVERSED,
+                };
+                style = if on { style.add_modifier(modifier) } else { style.remove_modifier(modifier) };
+            }
+            other => return Err(format!("未知的样式字段 {other}")),
+        }
+    }
+    Ok(style)
+}
+
+/// 颜色名(`red`, `lightblue` 等)或 `#rrggbb`
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim().to_ascii_lowercase();
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let v = u32::from_str_radix(hex, 16).ok()?;
+        return Some(Color::Rgb((v >> 16) as u8, (v >> 8) as u8, v as u8));
+    }
+    let color = match s.replace(['_', '-'], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parse_chords() {
+        assert_eq!(KeyChord::parse("ctrl-r"), Some(KeyChord { code: KeyCode::Char('r'), modifiers: KeyModifiers::CONTROL }));
+        assert_eq!(KeyChord::parse("Alt-Shift-X"), Some(KeyChord { code: KeyCode::Char('X'), modifiers: KeyModifiers::ALT | KeyModifiers::SHIFT }));
+        assert_eq!(KeyChord::parse("pageup"), Some(KeyChord { code: KeyCode::PageUp, modifiers: KeyModifiers::NONE }));
+        assert_eq!(KeyChord::parse("f12"), Some(KeyChord { code: KeyCode::F(12), modifiers: KeyModifiers::NONE }));
+        assert_eq!(KeyChord::parse("space"), Some(KeyChord { code: KeyCode::Char(' '), modifiers: KeyModifiers::NONE }));
+        // 单独的 `-` 是按键本身, 不是修饰键的一部分
+        assert_eq!(KeyChord::parse("ctrl--"), Some(KeyChord { code: KeyCode::Char('-'), modifiers: KeyModifiers::CONTROL }));
+        assert_eq!(KeyChord::parse("-"), Some(KeyChord { code: KeyCode::Char('-'), modifiers: KeyModifiers::NONE }));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys() {
+        assert_eq!(KeyChord::parse("ctrl-"), None);
+        assert_eq!(KeyChord::parse("hyper-x"), None);
+        assert_eq!(KeyChord::parse("fx"), None);
+        assert_eq!(KeyChord::parse(""), None);
+    }
+
+    #[test]
+    fn matches_modifiers() {
+        let chord = KeyChord::parse("ctrl-w").unwrap();
+        assert!(chord.matches(&key(KeyCode::Char('w'), KeyModifiers::CONTROL)));
+        assert!(!chord.matches(&key(KeyCode::Char('w'), KeyModifiers::NONE)));
+        assert!(!chord.matches(&key(KeyCode::Char('w'), KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        let pageup = KeyChord::parse("pageup").unwrap();
+        assert!(!pageup.matches(&key(KeyCode::PageUp, KeyModifiers::SHIFT)));
+    }
+
+    #[test]
+    fn shifted_symbols_match_without_shift_in_chord() {
+        // 多数终端在输入 `+` `?` 时会带上 Shift
+        let plus = KeyChord::parse("+").unwrap();
+        assert!(plus.matches(&key(KeyCode::Char('+'), KeyModifiers::SHIFT)));
+        assert!(plus.matches(&key(KeyCode::Char('+'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn explicit_shift_is_required() {
+        let chord = KeyChord::parse("shift-x").unwrap();
+        assert!(chord.matches(&key(KeyCode::Char('X'), KeyModifiers::SHIFT)));
+        assert!(!chord.matches(&key(KeyCode::Char('x'), KeyModifiers::NONE)));
+        assert!(!chord.matches(&key(KeyCode::Char('X'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn parse_config() {
+        let (config, warnings) = Config::parse(r##"
+            max_fps = 60
+            history_size = 100
+            [keys]
+            send_danmaku = "s"
+            next_page = ["ctrl-n", "pagedown"]
+            [colors.medal]
+            fg = "#fb7299"
+            bold = true
+        "##);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(config.max_fps, 60);
+        assert_eq!(config.history_size, 100);
+        let send: Vec<_> = config.keys.chords(Binding::SendDanmaku).map(ToString::to_string).collect();
+        assert_eq!(send, ["s"]);
+        assert_eq!(config.keys.chords(Binding::NextPage).count(), 2);
+        assert_eq!(config.theme.medal.fg, Some(Color::Rgb(0xfb, 0x72, 0x99)));
+        assert!(config.theme.medal.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn bad_entries_warn_and_keep_defaults() {
+        let (config, warnings) = Config::parse(r##"
+            max_fps = 0
+            nonsense = 1
+            [keys]
+            send_danmaku = "ctrl-"
+            fly = "f"
+            [colors.medal]
+            fg = "octarine"
+        "##);
+        assert_eq!(warnings.len(), 5, "{warnings:?}");
+        assert_eq!(config.max_fps, DEFAULT_MAX_FPS);
+        assert_eq!(config.keys.chords(Binding::SendDanmaku).map(ToString::to_string).collect::<Vec<_>>(), ["t"]);
+        assert_eq!(config.theme.medal, crate::style::MEDAL);
+    }
+
+    #[test]
+    fn deprecated_tick_rate_is_ignored() {
+        let (config, warnings) = Config::parse("tick_rate_ms = 250\nhistory_size = 100");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("tick_rate_ms"));
+        assert_eq!(config.max_fps, DEFAULT_MAX_FPS);
+        assert_eq!(config.history_size, 100);
+    }
+
+    #[test]
+    fn broken_toml_falls_back_to_defaults() {
+        let (config, warnings) = Config::parse("max_fps = ");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(config.max_fps, DEFAULT_MAX_FPS);
+    }
+}
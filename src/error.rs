@@ -1,8 +1,34 @@
+use std::fmt::Display;
+
 use bilibili_client::ClientError;
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum Error {
-    ConnectLiveRoomFail,
+    ConnectLiveRoomFail(u64),
     WebApiClientFail(ClientError),
-    Io(std::io::Error)
-}
\ No newline at end of file
+    Io(std::io::Error),
+    InvalidProfileName(String),
+    LoginFail(String),
+    InvalidRoomId(String),
+    DanmakuTooLong { len: usize, max: usize },
+    SendDanmakuFail { roomid: u64, reason: String },
+    InvalidFilterRule(String),
+    ModerationFail { action: String, reason: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ConnectLiveRoomFail(roomid) => write!(f, "无法连接直播间 {roomid}"),
+            Error::WebApiClientFail(e) => write!(f, "web api 请求失败: {e:?}"),
+            Error::Io(e) => write!(f, "IO 错误: {e}"),
+            Error::InvalidProfileName(name) => write!(f, "无效的账号名: {name}"),
+            Error::LoginFail(reason) => write!(f, "登录失败: {reason}"),
+            Error::InvalidRoomId(s) => write!(f, "无效的房间号: {s}"),
+            Error::DanmakuTooLong { len, max } => write!(f, "弹幕过长: {len}/{max}"),
+            Error::SendDanmakuFail { roomid, reason } => write!(f, "发送到直播间 {roomid} 失败: {reason}"),
+            Error::ModerationFail { action, reason } => write!(f, "{action}失败: {reason}"),
+            Error::InvalidFilterRule(rule) => write!(f, "无效的过滤规则: {rule}, 用法: {}", crate::service::filter::FILTER_USAGE),
+        }
+    }
+}
@@ -1,27 +1,31 @@
-use std::{io::{self}};
-use bilibili_client::danmaku;
+use std::{io::{self}, path::{Path, PathBuf}};
+use clap::Parser;
 use futures::{StreamExt};
-use page::GlobalState;
-use service::webapi::{WebApiService};
+use page::{GlobalState, Level};
+use service::{webapi::WebApiService, profile::{self, Profile}};
+use view::{layout::{PaneLayout, FocusMark}, help::{self, Help}, line_editor::EditKey};
 
 use tui::{
     backend::{CrosstermBackend, Backend},
-    widgets::{Block, Borders, Tabs, Paragraph},
+    widgets::{Block, Borders, Tabs, Paragraph, List, ListItem},
     layout::{Layout, Constraint, Direction, Rect, Alignment},
-    Terminal, Frame, text::{Spans,Text}, style::{Style, Color}
+    Terminal, Frame, text::{Span,Spans,Text}, style::{Style, Color}
 };
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as XtEvent, KeyCode},
-    execute,
+    event::{self, DisableMouseCapture, EnableMouseCapture, EnableBracketedPaste, DisableBracketedPaste, EnableFocusChange, DisableFocusChange, Event as XtEvent, KeyCode},
+    execute, cursor,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use crate::{error::Error, page::{liveroom::LiveRoomPageService, PageService, Psh, login::{LoginPageService}}};
+use crate::{cli::{Cli, Command}, config::{Binding, Config}, error::Error, page::{user_info::CardState, timeline::TimelinePageService, liveroom::LiveRoomPageService, replay::ReplayPageService, connecting::ConnectingPageService, PageService, Psh, login::{LoginPageService}}, service::{record::Recorder, danmaku::DanmakuQueue, filter::FilterStore, moderation::{self, Moderation}, session::{Session, SavedPage}}};
 
 
 mod view;
 #[allow(dead_code)]
 mod style;
 mod page;
+mod config;
+mod cli;
+mod command;
 // mod event;
 mod error;
 mod service;
@@ -29,46 +33,115 @@ mod service;
 /// App holds the state of the application
 pub struct App {
     state: GlobalState,
-    #[allow(dead_code)]
     webapi_service: WebApiService,
+    config: Config,
+    /// 是否录制新打开的直播间
+    record: bool,
+    /// 后台任务向主循环报告事件和错误
+    events: tokio::sync::mpsc::UnboundedSender<Evnet>,
+    danmaku_queue: DanmakuQueue,
+    filters: FilterStore,
+    /// 分屏设置
+    panes: PaneLayout,
+    /// 最近一次保存的会话, 有变化时才写文件
+    session: Session,
+    /// 当前账号的昵称, 弹幕 @ 到它时高亮; 未登录或还没取到时为 `None`
+    account_name: Option<String>,
 }
 
 impl App {
-    fn new() -> Self{
-        let webapi_service = WebApiService::new().unwrap();
-        Self{
-            state: GlobalState::default(),
-            webapi_service
+    fn new(profile: Profile, config_path: &Path, events: tokio::sync::mpsc::UnboundedSender<Evnet>) -> Result<Self, Error> {
+        let webapi_service = WebApiService::new(profile)?;
+        let (config, warnings) = Config::load(config_path);
+        style::set_theme(config.theme);
+        let mut state = GlobalState {
+            redraw: Some(events.clone()),
+            ..Default::default()
+        };
+        let (filters, filter_warnings) = FilterStore::load();
+        let (panes, layout_warning) = service::layout::load();
+        for warning in warnings.into_iter().chain(filter_warnings).chain(layout_warning) {
+            state.warn(warning);
         }
+        let danmaku_queue = DanmakuQueue::new(std::time::Duration::from_millis(config.danmaku_cooldown_ms), events.clone());
+        Ok(Self{
+            state,
+            webapi_service,
+            config,
+            record: false,
+            events,
+            danmaku_queue,
+            filters,
+            panes,
+            session: Session::default(),
+            account_name: None,
+        })
     }
 
-    fn tabs(&self) -> Tabs {
+    /// 切换账号. 发送弹幕, 房管操作和刷新直播间资料都在用时取当前的客户端,
+    /// 只有登录页面持有打开时的客户端, 所以登录页面打开时不能切换(见 [`run_action`])
+    fn switch_profile(&mut self, name: &str) -> Result<(), Error> {
+        let profile = Profile::new(name);
+        self.webapi_service = WebApiService::new(profile)?;
+        self.account_name = None;
+        refresh_mentions(self);
+        fetch_account_name(self);
+        // 弹幕字数上限随账号不同, 重新获取
+        for (_, psh) in &self.state.pages {
+            if let Psh::LiveRoomPageService(p) = psh {
+                let roomid = p.watcher.borrow().roomid;
+                page::liveroom::spawn_fetch_room_info(roomid, self.webapi_service.bilibili.clone(), self.events.clone(), false);
+            }
+        }
+        self.webapi_service.profile.set_active().map_err(Error::Io)
+    }
+
+    fn tabs(&self) -> Tabs<'_> {
         let titles = self.state.pages.iter().map(|p|Spans::from(p.0.clone())).collect();
+        let title = format!("Tabs [账号: {}]", self.webapi_service.profile.name);
         let tabs = Tabs::new(titles)
             .select(self.state.current_page.unwrap_or(0))
-            .block(Block::default().title("Tabs").borders(Borders::ALL))
+            .block(Block::default().title(title).borders(Borders::ALL))
             .style(Style::default().fg(Color::White))
             .highlight_style(Style::default().fg(Color::Yellow))
             .divider("/");
         tabs
     }
 
-    fn render_page<B:Backend>(&self, f: &mut Frame<B>, area: Rect) {
-        match self.state.current_page {
-            Some(idx) => {
-                let (_, handle) = &self.state.pages[idx];
-                handle.render(f, area);
+    fn render_pages<B:Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let panes = self.panes.panes(area, &self.state.page_keys(), self.state.current_page);
+        if panes.is_empty() {
+            // let qrcode = self.webapi_service.watcher.qrcode.borrow().clone();
+            f.render_widget(Paragraph::new("WELCOME").alignment(Alignment::Center).style(style::theme().info), area);
+        }
+        for (idx, (_, handle)) in self.state.pages.iter().enumerate() {
+            handle.set_on_screen(panes.iter().any(|(shown, _)| *shown == idx));
+        }
+        let split = panes.len() > 1;
+        for (idx, pane) in panes {
+            let (_, handle) = &self.state.pages[idx];
+            handle.render(f, pane);
+            if split && Some(idx) == self.state.current_page {
+                f.render_widget(FocusMark(style::theme().focus), pane);
             }
-            None => {
-                // let qrcode = self.webapi_service.watcher.qrcode.borrow().clone();
-                f.render_widget(Paragraph::new("WELCOME").alignment(Alignment::Center).style(style::INFO), area);
-            },
         }
     }
 
     fn render_message<B:Backend>(&self, f: &mut Frame<B>, area: Rect) {
-        let msg = self.state.messages.last().map(|x|x.as_str()).unwrap_or_default();
-        self.render_single_line_input(f, area, msg);
+        if let Some(msg) = self.state.messages.last() {
+            let p = Paragraph::new(msg.text.as_str()).style(level_style(msg.level));
+            f.render_widget(p, area);
+        }
+    }
+
+    fn render_log<B:Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let skip = self.state.messages.len().saturating_sub(height);
+        let items: Vec<ListItem> = self.state.messages.iter().skip(skip).map(|msg|{
+            ListItem::new(msg.text.as_str()).style(level_style(msg.level))
+        }).collect();
+        let list = List::new(items).block(Block::default().title("日志").borders(Borders::ALL));
+        f.render_widget(list, area);
     }
 
     fn render_single_line_input<'a, B:Backend>(&self, f: &mut Frame<B>, area: Rect, text: impl Into<Text<'a>>) {
@@ -78,43 +151,200 @@ impl App {
 }
 
 
+fn level_style(level: Level) -> Style {
+    match level {
+        Level::Info => Style::default(),
+        Level::Warn => style::theme().warn,
+        Level::Error => style::theme().error,
+    }
+}
+
+/// 界面各部分的位置, 绘制和鼠标点击共用
+struct AppLayout {
+    tabs: Rect,
+    page: Rect,
+    log: Option<Rect>,
+    hints: Rect,
+    input: Rect,
+}
+
+impl AppLayout {
+    fn new(size: Rect, app: &App) -> Self {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(2)
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Min(10),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(size);
+        let (page, log) = if app.state.show_log {
+            let page_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(5), Constraint::Length(10)].as_ref())
+                .split(chunks[1]);
+            (page_chunks[0], Some(page_chunks[1]))
+        } else {
+            (chunks[1], None)
+        };
+        Self {
+            tabs: chunks[0],
+            page,
+            log,
+            hints: chunks[2],
+            input: chunks[3],
+        }
+    }
+
+    /// 点击位置落在哪个标签上, 与 `Tabs` 的排布一致: 每个标题两侧各一格空白, 标题之间一格分隔符
+    fn tab_at(&self, app: &App, column: u16, row: u16) -> Option<usize> {
+        let inner = Block::default().borders(Borders::ALL).inner(self.tabs);
+        if row < inner.top() || row >= inner.bottom() {
+            return None;
+        }
+        let mut x = inner.left();
+        for (idx, (title, _)) in app.state.pages.iter().enumerate() {
+            let end = x + 2 + unicode_width::UnicodeWidthStr::width(title.as_str()) as u16;
+            if column >= x && column < end {
+                return Some(idx);
+            }
+            x = end + 1;
+        }
+        None
+    }
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.left() && column < area.right() && row >= area.top() && row < area.bottom()
+}
+
+const MAX_HINT_CANDIDATES: usize = 6;
+
+/// 提示行, 随输入状态和当前页面变化
+fn hints(app: &App) -> Spans<'static> {
+    let keys = &app.config.keys;
+    match &app.state.input_state {
+        page::InputState::EditAction { action: page::Action::Command, buffer, .. } => {
+            let candidates = command::complete(buffer.text(), &known_rooms(app));
+            // 没有可补全的内容时显示命令用法
+            if candidates.is_empty() {
+                let name = buffer.text().split_whitespace().next().unwrap_or_default();
+                if let Some(command::Resolved::Command(spec)) = command::resolve(name) {
+                    return Spans::from(Span::from(format!(" 用法: {}", spec.usage)));
+                }
+            }
+            let spans = candidates.into_iter().take(MAX_HINT_CANDIDATES).flat_map(|c|[
+                Span::styled(format!(" {} ", c.value), style::theme().inv),
+                Span::from(format!(" {}  ", c.label)),
+            ]);
+            return Spans::from(spans.collect::<Vec<_>>());
+        },
+        page::InputState::EditAction { .. } => {
+            return help::edit_hint_line(&[EditKey::Submit, EditKey::Cancel, EditKey::HistoryPrev, EditKey::DeleteWord]);
+        },
+        page::InputState::Confirm(_) => return Spans::from(vec![
+            Span::styled(" y ", style::theme().inv),
+            Span::from(" 确认  其它键取消"),
+        ]),
+        page::InputState::Normal => {},
+    }
+    let bindings: &[Binding] = match app.state.current_page_psh() {
+        Some(Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p)) if p.watcher.borrow().replay.is_some() => {
+            &[Binding::TogglePause, Binding::SpeedUp, Binding::SpeedDown, Binding::SelectPrev, Binding::UserInfo, Binding::Help]
+        },
+        Some(Psh::LiveRoomPageService(_)) => {
+            &[Binding::SendDanmaku, Binding::SelectPrev, Binding::Reply, Binding::UserInfo, Binding::EditFilter, Binding::Help]
+        },
+        Some(Psh::TimelinePageService(_)) => &[Binding::ScrollUp, Binding::ScrollDown, Binding::Follow, Binding::CycleLayout, Binding::Help],
+        Some(Psh::ConnectingPageService(h)) if h.watcher.borrow().is_failed() => &[Binding::Retry, Binding::ClosePage, Binding::Help],
+        _ => &[Binding::OpenRoom, Binding::OpenTimeline, Binding::Login, Binding::CommandPalette, Binding::Help, Binding::Quit],
+    };
+    help::hint_line(keys, bindings)
+}
+
 fn render<B:Backend>(f: &mut Frame<B>, app: &App) {
     let tabs = app.tabs();
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(2)
-        .constraints(
-            [
-                Constraint::Length(3),
-                Constraint::Min(10),
-                Constraint::Length(1),
-            ]
-            .as_ref(),
-        )
-        .split(f.size());
-
-    f.render_widget(tabs, chunks[0]);
-    app.render_page(f, chunks[1]);
+    let layout = AppLayout::new(f.size(), app);
+
+    f.render_widget(tabs, layout.tabs);
+    app.render_pages(f, layout.page);
+    if let Some(log) = layout.log {
+        app.render_log(f, log);
+    }
+    if app.state.show_help {
+        f.render_widget(Help { keys: &app.config.keys }, layout.page);
+    }
+    f.render_widget(Paragraph::new(hints(app)), layout.hints);
     match &app.state.input_state {
-        page::InputState::EditAction { action, display:_, buffer } => {
-            let display = format!("[{action}]:{buffer}");
-            app.render_single_line_input(f, chunks[2], display);
+        page::InputState::EditAction { action, buffer, .. } => {
+            let prefix = format!("[{action}]:");
+            let prefix_width = unicode_width::UnicodeWidthStr::width(prefix.as_str()) as u16;
+            let mut input = layout.input;
+            // 发送弹幕时在右侧显示字数
+            if let page::Action::SendDanmakuToLive(roomid) = action {
+                let len = buffer.text().chars().count();
+                let max = danmaku_max_length(app, *roomid);
+                let counter = format!(" {len}/{max}");
+                let counter_width = (counter.len() as u16).min(input.width);
+                let style = if len > max { style::theme().error } else { Style::default() };
+                let area = Rect { x: input.right() - counter_width, width: counter_width, ..input };
+                f.render_widget(Paragraph::new(Span::styled(counter, style)), area);
+                input.width -= counter_width;
+            }
+            let (visible, cursor) = buffer.visible(input.width.saturating_sub(prefix_width) as usize);
+            app.render_single_line_input(f, input, format!("{prefix}{visible}"));
+            f.set_cursor(input.x + prefix_width + cursor as u16, input.y);
+        },
+        page::InputState::Confirm(moderation) => {
+            let prompt = Span::styled(format!("确认{moderation}? [y/N]"), style::theme().warn);
+            app.render_single_line_input(f, layout.input, Spans::from(prompt));
         },
         page::InputState::Normal => {
-            app.render_message(f, chunks[2]);
+            app.render_message(f, layout.input);
         },
     }
 }
 
-#[derive(Debug)]
 pub enum Evnet {
-    Tick,
+    /// 页面状态有变化, 需要重绘
+    Redraw,
     Xt(XtEvent),
-    Error
+    Error(Error),
+    /// 后台连接的直播间已就绪, 替换编号为 `ticket` 的占位页
+    LiveRoomReady {
+        ticket: u64,
+        service: LiveRoomPageService,
+    },
+    /// 后台任务的结果, 显示在消息行
+    Message(String),
+    /// 直播间页面请求刷新直播间资料, `report_error` 为是否提示获取失败
+    FetchRoomInfo {
+        roomid: u64,
+        report_error: bool,
+    },
+    /// 直播间资料获取完成
+    RoomInfo {
+        roomid: u64,
+        info: service::webapi::RoomInfo,
+    },
+    /// 弹幕发送队列报告一条弹幕的发送结果
+    DanmakuSent {
+        roomid: u64,
+        text: String,
+        result: Result<(), String>,
+    },
+    /// 扫码登录成功
+    LoggedIn,
+    /// 取到了当前账号的昵称
+    AccountName(String),
 }
 
 pub struct EventCable {
-    ticker: tokio::time::Interval,
     oubound: tokio::sync::mpsc::UnboundedSender<Evnet>,
 }
 
@@ -124,31 +354,22 @@ impl EventCable {
         // for xterm event
         tokio::spawn(async move {
             let mut reader = event::EventStream::new();
-            while let Some(Ok(e)) = reader.next().await {
-                ob.send(Evnet::Xt(e)).unwrap_or_default()
-            }
-        });
-        // for ticker
-        let ob = self.oubound.clone();
-        let mut ticker = self.ticker;
-        tokio::spawn(async move {
-            loop {
-                ticker.tick().await;
-                ob.send(Evnet::Tick).unwrap_or_default()
+            while let Some(e) = reader.next().await {
+                match e {
+                    Ok(e) => ob.send(Evnet::Xt(e)).unwrap_or_default(),
+                    Err(e) => ob.send(Evnet::Error(Error::Io(e))).unwrap_or_default(),
+                }
             }
         });
     }
 }
 // 此处逻辑需要拆分
-async fn run<B:Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<(), Error> {
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+async fn run<B:Backend>(app: &mut App, terminal: &mut Terminal<B>, mut rx: tokio::sync::mpsc::UnboundedReceiver<Evnet>, rooms: Vec<u64>, replays: Vec<PathBuf>, timeline: Vec<u64>, restore: bool) -> Result<(), Error> {
     let cable = EventCable {
-        ticker: tokio::time::interval(tokio::time::Duration::from_millis(1000)),
-        oubound: tx
+        oubound: app.events.clone()
     };
     tokio::spawn(cable.run());
-    let webapi_service = crate::service::webapi::WebApiService::new()?;
-    // let mut rerender_timer = tokio::time::interval(tokio::time::Duration::from_millis(500));
+    fetch_account_name(app);
     // let online = { webapi_service.bilibili.is_online() };
     // if !online {
     //     let oauth_key = webapi_service.fetch_qrcode().await?;
@@ -156,162 +377,997 @@ async fn run<B:Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<(),
     //         webapi_service.try_login(oauth_key)
     //     }
     // }
-    terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
+    if restore {
+        let (session, warning) = Session::load();
+        if let Some(warning) = warning {
+            app.state.warn(warning);
+        }
+        restore_session(app, session);
+    }
+    for roomid in rooms {
+        // 已经从会话中恢复的直播间不再重复打开
+        let restored = app.state.pages.iter().any(|(_, psh)|match psh {
+            Psh::ConnectingPageService(h) => h.watcher.borrow().roomid == roomid,
+            _ => false,
+        });
+        if !restored {
+            open_live_room(app, roomid);
+        }
+    }
+    for path in replays {
+        open_replay(app, &path);
+    }
+    if !timeline.is_empty() {
+        open_timeline(app, timeline);
+    }
+    // 两帧之间的最短间隔, 期间的变化合并到下一帧
+    let frame = tokio::time::Duration::from_millis(1000 / app.config.max_fps);
+    terminal.draw(|f|render(f, app)).map_err(Error::Io)?;
+    let mut last_frame = tokio::time::Instant::now();
+    let mut dirty = false;
     // Racing
-    while let Some(e) = rx.recv().await {
+    loop {
+        let e = if dirty {
+            tokio::select! {
+                e = rx.recv() => e,
+                _ = tokio::time::sleep_until(last_frame + frame) => {
+                    terminal.draw(|f|render(f, app)).map_err(Error::Io)?;
+                    last_frame = tokio::time::Instant::now();
+                    dirty = false;
+                    continue;
+                }
+            }
+        } else {
+            rx.recv().await
+        };
+        let Some(e) = e else {
+            break;
+        };
+        // 重绘请求很频繁, 不会改变打开的页面
+        let may_change_session = !matches!(e, Evnet::Redraw);
         match e {
-            Evnet::Tick => {
-                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-            },
+            Evnet::Redraw => {},
             Evnet::Xt(e) => {
                 match e {
-                    XtEvent::Key(key_evt) => {
-                        use KeyCode::*;
-                        use event::KeyEventKind::*;
-                        use event::KeyModifiers;
-                        use page::Action;
-                        match (key_evt.code, key_evt.kind, key_evt.modifiers) {
-                            (Char('c'), Press, KeyModifiers::CONTROL) => {
-                                return Ok(())
-                            }
-                            (Char('w'), Press, KeyModifiers::CONTROL) => {
-                                app.state.close_page();
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                            }
-                            (Char('r'), Press, KeyModifiers::CONTROL) => {
-                                app.state.input_state = page::InputState::edit_action(Action::CreatLiveRoomPage);
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                            }
-                            (Char('l'), Press, KeyModifiers::CONTROL) => {
-                                let srv = LoginPageService::new(&webapi_service.bilibili);
-                                let psh = Psh::LoginPageService(srv.run());
-                                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                                app.state.message(format!("psh is running"));
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                                app.state.regist_page(format!("登录"), psh);
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
+                    XtEvent::Key(key_evt) if key_evt.kind == event::KeyEventKind::Press => {
+                        match handle_key(app, key_evt).await {
+                            Ok(true) => {
+                                save_session(app);
+                                return Ok(());
+                            },
+                            Ok(false) => {},
+                            Err(e) => app.state.error(e),
+                        }
+                    }
+                    XtEvent::Mouse(mouse_evt) => {
+                        let size = terminal.size().map_err(Error::Io)?;
+                        // 鼠标移动等没有效果的事件不重绘, 空闲时不占用 CPU
+                        if !handle_mouse(app, mouse_evt, size) {
+                            continue;
+                        }
+                    }
+                    XtEvent::Paste(text) => {
+                        let page::InputState::EditAction { buffer, .. } = &mut app.state.input_state else {
+                            continue;
+                        };
+                        // 输入行只有一行, 换行折成空格
+                        buffer.insert_str(&text.replace(['\r', '\n'], " "));
+                    }
+                    XtEvent::Resize(_, _) => {
+                        // 尺寸变化立即重排, 不等下一帧
+                        terminal.autoresize().map_err(Error::Io)?;
+                        terminal.draw(|f|render(f, app)).map_err(Error::Io)?;
+                        last_frame = tokio::time::Instant::now();
+                        continue;
+                    }
+                    XtEvent::FocusGained => {
+                        // 失去焦点期间终端可能被其他程序弄乱, 整屏重画
+                        terminal.clear().map_err(Error::Io)?;
+                    }
+                    // 按键松开, 失去焦点等
+                    _ => continue,
+                }
+            },
+            Evnet::Error(e) => {
+                app.state.error(e);
+            },
+            Evnet::LiveRoomReady { ticket, service } => {
+                attach_live_room(app, ticket, service);
+            },
+            Evnet::Message(text) => {
+                app.state.message(text);
+            },
+            Evnet::FetchRoomInfo { roomid, report_error } => {
+                page::liveroom::spawn_fetch_room_info(roomid, app.webapi_service.bilibili.clone(), app.events.clone(), report_error);
+            },
+            Evnet::RoomInfo { roomid, info } => {
+                apply_room_info(app, roomid, info);
+            },
+            Evnet::LoggedIn => {
+                // 和 `biliterm login` 一样, 登录成功的账号下次启动时默认使用
+                if let Err(e) = app.webapi_service.profile.set_active() {
+                    app.state.error(Error::Io(e));
+                }
+                fetch_account_name(app);
+            },
+            Evnet::AccountName(name) => {
+                app.account_name = Some(name);
+                refresh_mentions(app);
+            },
+            Evnet::DanmakuSent { roomid, text, result } => {
+                match result {
+                    Ok(()) => app.state.message(format!("已发送: {text}")),
+                    Err(reason) => {
+                        app.state.error(Error::SendDanmakuFail { roomid, reason });
+                        app.state.failed_danmaku.push((roomid, text));
+                    }
+                }
+            },
+        }
+        if may_change_session {
+            save_session(app);
+        }
+        dirty = true;
+    }
+    Ok(())
+}
+
+const MOUSE_SCROLL_LINES: usize = 3;
+
+/// 处理一次鼠标事件, 返回是否有变化需要重绘
+fn handle_mouse(app: &mut App, mouse_evt: event::MouseEvent, size: Rect) -> bool {
+    use event::{MouseEventKind, MouseButton};
+    let layout = AppLayout::new(size, app);
+    let (column, row) = (mouse_evt.column, mouse_evt.row);
+    match mouse_evt.kind {
+        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown if contains(layout.page, column, row) => {
+            // 滚动鼠标所在的窗格
+            let pane = app.panes.panes(layout.page, &app.state.page_keys(), app.state.current_page)
+                .into_iter().find(|(_, area)|contains(*area, column, row)).map(|(idx, _)|idx);
+            match pane.map(|idx|&app.state.pages[idx].1) {
+                Some(Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p)) => match mouse_evt.kind {
+                    MouseEventKind::ScrollUp => p.updater.send_modify(|p|p.scroll_up(MOUSE_SCROLL_LINES)),
+                    _ => p.updater.send_modify(|p|p.scroll_down(MOUSE_SCROLL_LINES)),
+                },
+                Some(Psh::TimelinePageService(p)) => match mouse_evt.kind {
+                    MouseEventKind::ScrollUp => p.updater.send_modify(|p|p.scroll_up(MOUSE_SCROLL_LINES)),
+                    _ => p.updater.send_modify(|p|p.scroll_down(MOUSE_SCROLL_LINES)),
+                },
+                _ => return false,
+            }
+            true
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            let clicked = layout.tab_at(app, column, row).or_else(||{
+                // 点击窗格使其获得焦点
+                let panes = app.panes.panes(layout.page, &app.state.page_keys(), app.state.current_page);
+                panes.into_iter().find(|(_, area)|contains(*area, column, row)).map(|(idx, _)|idx)
+            });
+            match clicked {
+                Some(idx) if app.state.current_page != Some(idx) => {
+                    app.state.current_page = Some(idx);
+                    true
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// 处理一次按键, 返回 `true` 表示退出
+async fn handle_key(app: &mut App, key_evt: event::KeyEvent) -> Result<bool, Error> {
+    use KeyCode::*;
+    use event::KeyModifiers;
+    // 帮助界面打开时任意键关闭
+    if app.state.show_help {
+        app.state.show_help = false;
+        return Ok(false);
+    }
+    // 编辑输入行时, 编辑键优先于按键表
+    if let page::InputState::EditAction { action, buffer, .. } = &mut app.state.input_state {
+        match (EditKey::from_event(&key_evt), key_evt.code) {
+            (Some(EditKey::Submit), _) => return submit_input(app).await,
+            (Some(EditKey::Cancel), _) => app.state.input_state = page::InputState::Normal,
+            (Some(EditKey::Left), _) => buffer.left(),
+            (Some(EditKey::Right), _) => buffer.right(),
+            (Some(EditKey::Home), _) => buffer.home(),
+            (Some(EditKey::End), _) => buffer.end(),
+            (Some(EditKey::Backspace), _) => buffer.backspace(),
+            (Some(EditKey::Delete), _) => buffer.delete(),
+            (Some(EditKey::DeleteWord), _) => buffer.delete_word(),
+            (Some(EditKey::KillToStart), _) => buffer.kill_to_start(),
+            (Some(EditKey::HistoryPrev), _) => app.state.history_prev(),
+            (Some(EditKey::HistoryNext), _) => app.state.history_next(),
+            // 命令面板中补全最后一个词
+            (Some(EditKey::Complete), _) if matches!(action, page::Action::Command) => complete_command(app),
+            (None, Char(c)) if matches!(key_evt.modifiers, KeyModifiers::NONE|KeyModifiers::SHIFT) => buffer.insert(c),
+            _ => return handle_global_key(app, key_evt).await,
+        }
+        return Ok(false);
+    }
+    if matches!(app.state.input_state, page::InputState::Confirm(_)) {
+        if let page::InputState::Confirm(moderation) = std::mem::take(&mut app.state.input_state) {
+            match key_evt.code {
+                Char('y'|'Y') => {
+                    app.state.message(format!("正在{moderation}"));
+                    moderation.spawn(app.webapi_service.bilibili.clone(), app.events.clone());
+                },
+                _ => app.state.message("已取消"),
+            }
+        }
+        return Ok(false);
+    }
+    handle_global_key(app, key_evt).await
+}
+
+async fn handle_global_key(app: &mut App, key_evt: event::KeyEvent) -> Result<bool, Error> {
+    let on_live_room = app.state.is_normal_on_live_room();
+    let binding = app.config.keys.matching(&key_evt).find(|b|on_live_room || !b.is_live_room());
+    match binding {
+        // 按键和命令面板走同一条路径
+        Some(binding) => run_command(app, command::binding_command(binding)).await,
+        None => Ok(false)
+    }
+}
+
+async fn handle_binding(app: &mut App, binding: Binding) -> Result<bool, Error> {
+    use page::Action;
+    match binding {
+        Binding::Quit => {
+            return Ok(true)
+        }
+        Binding::ClosePage => {
+            if let Some((_, psh)) = app.state.current_page.map(|idx| &app.state.pages[idx]) {
+                app.panes.remove(&psh.key());
+            }
+            app.state.close_page();
+        }
+        Binding::OpenRoom => {
+            app.state.input_state = page::InputState::edit_action(Action::CreatLiveRoomPage);
+        }
+        Binding::Login => {
+            let srv = LoginPageService::new(&app.webapi_service.bilibili);
+            let handle = srv.run();
+            // 登录成功后重新获取账号昵称
+            let mut watcher = handle.watcher.clone();
+            let events = app.events.clone();
+            tokio::spawn(async move {
+                while watcher.changed().await.is_ok() {
+                    if watcher.borrow().success {
+                        events.send(Evnet::LoggedIn).unwrap_or_default();
+                        break;
+                    }
+                }
+            });
+            app.state.regist_page("登录".to_string(), Psh::LoginPageService(handle));
+        }
+        Binding::SwitchProfile => {
+            app.state.input_state = page::InputState::edit_action(Action::SwitchProfile);
+            app.state.message(format!("已有账号: {}", Profile::list().join(", ")));
+        }
+        Binding::OpenReplay => {
+            app.state.input_state = page::InputState::edit_action(Action::OpenReplay);
+        }
+        Binding::CycleLayout => {
+            app.panes.cycle();
+            app.state.message(format!("布局: {}", app.panes.mode.name()));
+            save_layout(app);
+        }
+        Binding::GrowPane | Binding::ShrinkPane => {
+            if let Some((_, psh)) = app.state.current_page.map(|idx| &app.state.pages[idx]) {
+                let key = psh.key();
+                app.panes.resize(&key, if binding == Binding::GrowPane { 1 } else { -1 });
+                save_layout(app);
+            }
+        }
+        Binding::ToggleLog => {
+            app.state.show_log = !app.state.show_log;
+        }
+        Binding::Retry => {
+            retry_live_room(app);
+        }
+        Binding::NextPage => {
+            app.state.select_next_page();
+        }
+        Binding::PrevPage => {
+            app.state.select_prev_page();
+        }
+        Binding::OpenTimeline => {
+            app.state.input_state = page::InputState::edit_action(Action::OpenTimeline);
+        }
+        Binding::Help => {
+            app.state.show_help = true;
+        }
+        Binding::CommandPalette => {
+            app.state.input_state = page::InputState::edit_action(Action::Command);
+        }
+        live_room_binding => {
+            // 时间线只支持滚动
+            if let Some(Psh::TimelinePageService(p)) = app.state.current_page_psh() {
+                match live_room_binding {
+                    Binding::ScrollUp => p.updater.send_modify(|p|p.scroll_up(1)),
+                    Binding::ScrollDown => p.updater.send_modify(|p|p.scroll_down(1)),
+                    Binding::ScrollPageUp => p.updater.send_modify(|p|p.page_up()),
+                    Binding::ScrollPageDown => p.updater.send_modify(|p|p.page_down()),
+                    Binding::Follow => p.updater.send_modify(|p|p.follow()),
+                    _ => {}
+                }
+            }
+            if let Some(Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p)) = app.state.current_page_psh() {
+                match live_room_binding {
+                    Binding::SendDanmaku => {
+                        let (roomid, is_replay) = {
+                            let page = p.watcher.borrow();
+                            (page.roomid, page.replay.is_some())
+                        };
+                        if !is_replay {
+                            app.state.input_state = page::InputState::edit_action(Action::SendDanmakuToLive(roomid));
+                        }
+                    }
+                    Binding::ResendFailed => {
+                        let roomid = p.watcher.borrow().roomid;
+                        resend_failed(app, roomid);
+                    }
+                    Binding::Reply => {
+                        let (roomid, is_replay, uname) = {
+                            let page = p.watcher.borrow();
+                            let uname = page.selected_event().and_then(page::liveroom::event_user).map(|u|u.uname.clone());
+                            (page.roomid, page.replay.is_some(), uname)
+                        };
+                        match uname {
+                            Some(uname) if !is_replay => {
+                                let mut input = page::InputState::edit_action(Action::SendDanmakuToLive(roomid));
+                                if let page::InputState::EditAction { buffer, .. } = &mut input {
+                                    buffer.set_text(format!("@{uname} "));
+                                }
+                                app.state.input_state = input;
                             }
-                            (Char(',')|Tab, Press, KeyModifiers::CONTROL)|(PageDown, Press, KeyModifiers::NONE) => {
-                                app.state.to_next_page();
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
+                            Some(_) => {}
+                            None => app.state.message("先用 ↑/↓ 选中一条弹幕"),
+                        }
+                    }
+                    Binding::EditFilter => {
+                        let roomid = p.watcher.borrow().roomid;
+                        app.state.input_state = page::InputState::edit_action(Action::EditFilter(roomid));
+                    }
+                    Binding::HideUser => {
+                        let selected = {
+                            let page = p.watcher.borrow();
+                            page.selected_event().and_then(page::liveroom::event_user).map(|u|(page.roomid, u.uid, u.uname.clone()))
+                        };
+                        match selected {
+                            Some((roomid, uid, uname)) => {
+                                match app.filters.apply(roomid, &format!("hide-uid {uid}")) {
+                                    Ok(_) => {
+                                        app.state.message(format!("已在直播间 {roomid} 隐藏 {uname}({uid}) 的消息"));
+                                        filters_changed(app);
+                                    }
+                                    Err(e) => app.state.error(e),
+                                }
                             }
-                            (Char('.'), Press, KeyModifiers::CONTROL)|(PageUp, Press, KeyModifiers::NONE) => {
-                                app.state.to_prev_page();
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
+                            None => app.state.message("先用 ↑/↓ 选中一条弹幕"),
+                        }
+                    }
+                    Binding::Silence | Binding::Blacklist => {
+                        let selected = {
+                            let page = p.watcher.borrow();
+                            let user = page.selected_event().and_then(page::liveroom::event_user).map(|u|(u.uid, u.uname.clone()));
+                            user.filter(|_|page.replay.is_none()).map(|(uid, uname)|(page.roomid, uid, uname))
+                        };
+                        match (selected, live_room_binding) {
+                            (Some((roomid, uid, uname)), Binding::Silence) => {
+                                let mut input = page::InputState::edit_action(Action::Silence { roomid, uid, uname });
+                                if let page::InputState::EditAction { buffer, .. } = &mut input {
+                                    buffer.set_text("1");
+                                }
+                                app.state.input_state = input;
                             }
-                            (Char(c), Press, KeyModifiers::NONE) => {
-                                let next = match &mut app.state.input_state {
-                                    page::InputState::EditAction { action:_, display:_, buffer } => {
-                                        buffer.push(c);
-                                        terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                                        None
-                                    },
-                                    page::InputState::Normal => {
-                                        if let Some(Psh::LiveRoomPageService(p)) = app.state.current_page_psh() {
-                                            if c == 't' {
-                                                let next = page::InputState::edit_action(Action::SendDanmakuToLive(p.watcher.borrow().roomid));
-                                                Some(next)
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        }
-                                    },
-                                };
-                                next.map(|s|app.state.input_state = s);
+                            // 直播间的黑名单就是永久禁言, 直接确认
+                            (Some((roomid, uid, uname)), _) => {
+                                app.state.input_state = page::InputState::Confirm(Moderation::Mute { roomid, uid, uname, hours: -1 });
                             }
-                            (Backspace, Press, KeyModifiers::NONE) => {
-                                match &mut app.state.input_state {
-                                    page::InputState::EditAction { action:_, display:_, buffer } => {
-                                        buffer.pop();
-                                        terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                                    },
-                                    page::InputState::Normal => {
-    
-                                    },
-                                }
+                            (None, _) => app.state.message("先用 ↑/↓ 选中直播间中的一条弹幕"),
+                        }
+                    }
+                    Binding::ListMuted | Binding::Unmute => {
+                        let (roomid, is_replay) = {
+                            let page = p.watcher.borrow();
+                            (page.roomid, page.replay.is_some())
+                        };
+                        if !is_replay {
+                            if live_room_binding == Binding::ListMuted {
+                                Moderation::ListMuted { roomid }.spawn(app.webapi_service.bilibili.clone(), app.events.clone());
+                                app.state.show_log = true;
+                            } else {
+                                app.state.input_state = page::InputState::edit_action(Action::Unmute(roomid));
                             }
-                            (Esc, Press, KeyModifiers::NONE) => {
-                                match &mut app.state.input_state {
-                                    s@page::InputState::EditAction { .. } => {
-                                        *s = page::InputState::Normal;
-                                        terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
-                                    },
-                                    page::InputState::Normal => {
-    
-                                    },
-                                }
+                        }
+                    }
+                    Binding::UserInfo => {
+                        let mut uid = None;
+                        p.updater.send_modify(|p|{
+                            if p.user_info.take().is_none() {
+                                uid = p.open_user_info();
                             }
-                            (Enter, Press, KeyModifiers::NONE) => {
-                                let state= &mut app.state.input_state;
-                                match state {
-                                    page::InputState::EditAction { action, display:_, buffer } => {
-                                        match action {
-                                            Action::CreatLiveRoomPage => {
-                                                match buffer.parse::<u64>() {
-                                                    Ok(roomid) => {
-                                                        let srv = LiveRoomPageService::new(roomid).await.unwrap();
-                                                        app.state.regist_page(format!("直播{roomid}"), Psh::LiveRoomPageService(srv.run()));
-                                                    },
-                                                    Err(e) => {
-                                                        app.state.message(format!("{e}"))
-                                                    },
-                                                }
-                                            },
-                                            Action::SendDanmakuToLive(roomid) => {
-                                                webapi_service.bilibili.excute(bilibili_client::transaction::send_danmaku_to_live::SendDanmakuToLive {
-                                                    roomid: *roomid,
-                                                    danmaku: danmaku!(buffer.as_str())
-                                                });
-                                            },
+                        });
+                        match uid {
+                            Some(uid) => {
+                                let updater = p.updater.clone();
+                                let client = app.webapi_service.bilibili.clone();
+                                tokio::spawn(async move {
+                                    let card = match service::webapi::fetch_user_card(client, uid).await {
+                                        Ok(card) => CardState::Loaded(card),
+                                        Err(reason) => CardState::Failed(reason),
+                                    };
+                                    // 弹窗可能已经关闭或换了人
+                                    updater.send_modify(|p|{
+                                        if let Some(info) = p.user_info.as_mut().filter(|info|info.uid == uid) {
+                                            info.card = card;
                                         }
-                                    },
-                                    page::InputState::Normal => {},
-                                }
-                                app.state.input_state = page::InputState::Normal;
-                                terminal.draw(|f|render(f, &app)).map_err(Error::Io)?;
+                                    });
+                                });
                             }
-                            _ => {
-    
+                            None if p.watcher.borrow().selected.is_none() => {
+                                app.state.message("先用 ↑/↓ 选中一条弹幕");
                             }
+                            None => {}
                         }
                     }
-                    _ => {
-
+                    Binding::CopyMessage => {
+                        let text = p.watcher.borrow().selected_event().map(page::liveroom::message_text);
+                        match text {
+                            Some(text) => match view::clipboard::copy(&text) {
+                                Ok(()) => app.state.message(format!("已复制: {text}")),
+                                Err(e) => app.state.error(Error::Io(e)),
+                            },
+                            None => app.state.message("先用 ↑/↓ 选中一条弹幕"),
+                        }
                     }
-                    // XtEvent::Paste(_) => todo!(),
-                    // XtEvent::FocusGained => todo!(),
-                    // XtEvent::FocusLost => todo!(),
-                    // XtEvent::Mouse(_) => todo!(),
-                    // XtEvent::Resize(_, _) => todo!(),
+                    Binding::SelectPrev => p.updater.send_modify(|p|p.select_prev()),
+                    Binding::SelectNext => p.updater.send_modify(|p|p.select_next()),
+                    Binding::ClearSelection => p.updater.send_modify(|p|p.clear_selection()),
+                    Binding::ToggleEntries => p.updater.send_modify(|p|p.toggle_entries()),
+                    Binding::ScrollUp => p.updater.send_modify(|p|p.scroll_up(1)),
+                    Binding::ScrollDown => p.updater.send_modify(|p|p.scroll_down(1)),
+                    Binding::ScrollPageUp => p.updater.send_modify(|p|p.page_up()),
+                    Binding::ScrollPageDown => p.updater.send_modify(|p|p.page_down()),
+                    Binding::Follow => p.updater.send_modify(|p|p.follow()),
+                    Binding::TogglePause => p.updater.send_modify(|p|p.toggle_pause()),
+                    Binding::SpeedUp => p.updater.send_modify(|p|p.speed_up()),
+                    Binding::SpeedDown => p.updater.send_modify(|p|p.speed_down()),
+                    _ => {}
                 }
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// 先放一个占位标签页, 直播间在后台连接
+fn open_live_room(app: &mut App, roomid: u64) {
+    let srv = ConnectingPageService::new(roomid, app.events.clone());
+    app.state.regist_page(format!("直播{roomid}"), Psh::ConnectingPageService(srv.run()));
+}
+
+/// 重试当前失败的占位页
+fn retry_live_room(app: &mut App) {
+    let Some(idx) = app.state.current_page else {
+        return;
+    };
+    let (roomid, scroll) = match &app.state.pages[idx].1 {
+        Psh::ConnectingPageService(h) if h.watcher.borrow().is_failed() => {
+            let page = h.watcher.borrow();
+            (page.roomid, page.scroll)
+        },
+        _ => return,
+    };
+    let srv = ConnectingPageService::new(roomid, app.events.clone()).scroll(scroll);
+    app.state.replace_page(idx, Psh::ConnectingPageService(srv.run()));
+}
+
+fn attach_live_room(app: &mut App, ticket: u64, srv: LiveRoomPageService) {
+    // 占位页已被关闭
+    let Some(idx) = app.state.find_connecting_page(ticket) else {
+        return;
+    };
+    let scroll = match &app.state.pages[idx].1 {
+        Psh::ConnectingPageService(h) => h.watcher.borrow().scroll,
+        _ => 0,
+    };
+    let roomid = srv.roomid();
+    let mut srv = srv.history_size(app.config.history_size)
+        .mentions(mention_names(app))
+        .room_info(app.events.clone());
+    if app.record {
+        match Recorder::create(roomid) {
+            Ok(recorder) => {
+                app.state.message(format!("录制到 {}", recorder.path.display()));
+                srv = srv.record(recorder);
             },
-            Evnet::Error => todo!(),
+            Err(e) => app.state.warn(format!("无法创建录制文件: {e}")),
         }
     }
-    Ok(())
+    app.state.replace_page(idx, Psh::LiveRoomPageService(srv.run()));
+    if scroll > 0 {
+        app.state.pages[idx].1.restore_scroll(scroll);
+    }
+    refresh_filters(app);
+}
+
+/// 把直播间资料交给页面, 并用主播名作为标签页标题; 时间线用主播名标记消息
+fn apply_room_info(app: &mut App, roomid: u64, info: service::webapi::RoomInfo) {
+    for (title, psh) in &mut app.state.pages {
+        match psh {
+            Psh::LiveRoomPageService(p) if p.watcher.borrow().roomid == roomid => {
+                *title = format!("{}({roomid})", info.anchor);
+                let info = info.clone();
+                p.updater.send_modify(|p|p.header.info = Some(info));
+            },
+            Psh::TimelinePageService(p) if p.watcher.borrow().roomids.contains(&roomid) => {
+                p.updater.send_modify(|p|p.set_name(roomid, &info.anchor));
+            },
+            _ => {}
+        }
+    }
+}
+
+/// 弹幕中需要高亮的名字: 当前账号的昵称, 加上配置中额外的名字
+fn mention_names(app: &App) -> Vec<String> {
+    app.account_name.iter().chain(app.config.mention_names.iter()).cloned().collect()
+}
+
+/// 账号变化后把要高亮的名字下发到所有直播间和回放页面
+fn refresh_mentions(app: &App) {
+    let mentions = mention_names(app);
+    for (_, psh) in &app.state.pages {
+        if let Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p) = psh {
+            let mentions = mentions.clone();
+            p.updater.send_modify(|p|p.mentions = mentions);
+        }
+    }
+}
+
+/// 在后台获取当前账号的昵称, 未登录时不报错
+fn fetch_account_name(app: &App) {
+    let client = app.webapi_service.bilibili.clone();
+    let events = app.events.clone();
+    tokio::spawn(async move {
+        match service::webapi::fetch_account_name(client).await {
+            Ok(Some(name)) => events.send(Evnet::AccountName(name)),
+            Ok(None) => Ok(()),
+            Err(reason) => events.send(Evnet::Message(format!("无法获取账号信息: {reason}"))),
+        }.unwrap_or_default();
+    });
+}
+
+fn save_layout(app: &mut App) {
+    if let Err(e) = service::layout::save(&app.panes) {
+        app.state.error(Error::Io(e));
+    }
+}
+
+/// 把过滤规则下发到所有直播间, 回放和时间线页面
+fn refresh_filters(app: &App) {
+    for (_, psh) in &app.state.pages {
+        match psh {
+            Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p) => {
+                let filter = app.filters.filter_for(p.watcher.borrow().roomid);
+                p.updater.send_modify(|p|p.set_filter(filter));
+            },
+            Psh::TimelinePageService(p) => {
+                let filters = p.watcher.borrow().roomids.iter().map(|roomid| app.filters.filter_for(*roomid)).collect();
+                p.updater.send_modify(|p|p.set_filters(filters));
+            },
+            _ => {}
+        }
+    }
+}
+
+/// 规则有改动: 保存并刷新页面
+fn filters_changed(app: &mut App) {
+    if let Err(e) = app.filters.save() {
+        app.state.error(Error::Io(e));
+    }
+    refresh_filters(app);
+}
+
+fn open_replay(app: &mut App, path: &Path) {
+    match ReplayPageService::new(path) {
+        Ok((srv, broken)) => {
+            if broken > 0 {
+                app.state.warn(format!("{} 中有 {broken} 行无法解析, 已跳过", path.display()));
+            }
+            let srv = srv.history_size(app.config.history_size).mentions(mention_names(app));
+            let name = path.file_stem().map(|s|s.to_string_lossy().into_owned()).unwrap_or_default();
+            app.state.regist_page(format!("回放{name}"), Psh::ReplayPageService(srv.run()));
+            refresh_filters(app);
+        },
+        Err(e) => app.state.warn(format!("无法打开回放 {}: {e}", path.display())),
+    }
+}
+
+/// 恢复上次的标签页, 直播间在后台重新连接
+fn restore_session(app: &mut App, session: Session) {
+    // 会话中的位置 -> 恢复后的位置, 打不开的回放会被跳过
+    let mut restored = Vec::with_capacity(session.pages.len());
+    for entry in session.pages {
+        let before = app.state.pages.len();
+        match entry.page {
+            // 滚动位置先记在占位页上, 连接成功后交给直播间页面
+            SavedPage::Live { roomid } => open_live_room(app, roomid),
+            SavedPage::Replay { path, speed } => {
+                open_replay(app, &path);
+                if let Some((_, Psh::ReplayPageService(p))) = app.state.pages.last().filter(|_|app.state.pages.len() > before) {
+                    p.updater.send_modify(|p|if let Some(replay) = &mut p.replay {
+                        replay.speed = speed;
+                    });
+                }
+            },
+            SavedPage::Timeline { roomids } => open_timeline(app, roomids),
+        }
+        if app.state.pages.len() > before {
+            let (title, psh) = &mut app.state.pages[before];
+            *title = entry.title;
+            if entry.scroll > 0 {
+                psh.restore_scroll(entry.scroll);
+            }
+            if let Some(weight) = entry.weight {
+                app.panes.weights.insert(psh.key(), weight);
+            }
+            restored.push(Some(before));
+        } else {
+            restored.push(None);
+        }
+    }
+    if let Some(idx) = session.current_page.and_then(|idx|restored.get(idx).copied().flatten()) {
+        app.state.current_page = Some(idx);
+    }
+}
+
+/// 打开的页面有变化时保存会话
+fn save_session(app: &mut App) {
+    let session = app.state.session(&app.panes.weights);
+    if session == app.session {
+        return;
+    }
+    // 无论成败都记下, 避免每个事件都重试并刷屏
+    let result = session.save();
+    app.session = session;
+    if let Err(e) = result {
+        app.state.error(Error::Io(e));
+    }
+}
+
+fn open_timeline(app: &mut App, mut roomids: Vec<u64>) {
+    // 重复的房间号只连接一次
+    let mut seen = std::collections::HashSet::new();
+    roomids.retain(|roomid|seen.insert(*roomid));
+    let title = format!("时间线{}", roomids.len());
+    // 主播名用来标记消息来自哪个直播间
+    for &roomid in &roomids {
+        page::liveroom::spawn_fetch_room_info(roomid, app.webapi_service.bilibili.clone(), app.events.clone(), true);
+    }
+    let srv = TimelinePageService::new(roomids).history_size(app.config.history_size);
+    app.state.regist_page(title, Psh::TimelinePageService(srv.run()));
+    refresh_filters(app);
+}
+
+/// 重新排队发送该直播间所有发送失败的弹幕
+fn resend_failed(app: &mut App, roomid: u64) {
+    let (failed, rest) = std::mem::take(&mut app.state.failed_danmaku).into_iter().partition::<Vec<_>, _>(|(id, _)|*id == roomid);
+    app.state.failed_danmaku = rest;
+    if failed.is_empty() {
+        app.state.message("没有发送失败的弹幕");
+        return;
+    }
+    let count = failed.len();
+    for (roomid, text) in failed {
+        app.danmaku_queue.send(&app.webapi_service.bilibili, roomid, text);
+    }
+    app.state.message(format!("重新发送 {count} 条弹幕"));
+}
+
+/// 已知的直播间: 打开的标签页和打开过的房间号, 用于补全
+fn known_rooms(app: &App) -> Vec<(u64, String)> {
+    let mut rooms: Vec<(u64, String)> = Vec::new();
+    for (title, psh) in &app.state.pages {
+        let roomid = match psh {
+            Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p) => p.watcher.borrow().roomid,
+            Psh::ConnectingPageService(h) => h.watcher.borrow().roomid,
+            _ => continue,
+        };
+        rooms.push((roomid, title.clone()));
+    }
+    let history = app.state.input_history.get(&std::mem::discriminant(&page::Action::CreatLiveRoomPage));
+    for roomid in history.into_iter().flatten().rev().filter_map(|line|line.trim().parse::<u64>().ok()) {
+        rooms.push((roomid, "打开过".to_string()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    rooms.retain(|(roomid, _)|seen.insert(*roomid));
+    rooms
+}
+
+/// 把命令行的最后一个词补全为最佳候选
+fn complete_command(app: &mut App) {
+    let rooms = known_rooms(app);
+    if let page::InputState::EditAction { buffer, .. } = &mut app.state.input_state {
+        if let Some(candidate) = command::complete(buffer.text(), &rooms).first() {
+            let line = command::apply_candidate(buffer.text(), candidate);
+            buffer.set_text(line);
+        }
+    }
+}
+
+/// 当前直播间或回放页面的房间号, 以及是否为回放
+fn current_room(app: &App) -> Option<(u64, bool)> {
+    match app.state.current_page_psh() {
+        Some(Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p)) => {
+            let page = p.watcher.borrow();
+            Some((page.roomid, page.replay.is_some()))
+        },
+        _ => None,
+    }
+}
+
+/// 执行命令面板中的一行, 返回 `true` 表示退出
+async fn run_command(app: &mut App, line: &str) -> Result<bool, Error> {
+    use page::Action;
+    let line = line.trim();
+    let (name, args) = line.split_once(' ').map(|(name, args)|(name, args.trim())).unwrap_or((line, ""));
+    if name.is_empty() {
+        return Ok(false);
+    }
+    let spec = match command::resolve(name) {
+        Some(command::Resolved::Binding(binding)) => return handle_binding(app, binding).await,
+        Some(command::Resolved::Command(spec)) => spec,
+        None => {
+            app.state.warn(format!("未知命令: {name}"));
+            return Ok(false);
+        },
+    };
+    // 不带参数时与按键相同
+    if let Some(binding) = spec.binding.filter(|_|args.is_empty()) {
+        return handle_binding(app, binding).await;
+    }
+    match spec.name {
+        "open" => {
+            for roomid in args.split_whitespace() {
+                run_action(app, Action::CreatLiveRoomPage, roomid.to_string());
+            }
+        },
+        "send" => match current_room(app) {
+            Some((roomid, false)) => run_action(app, Action::SendDanmakuToLive(roomid), args.to_string()),
+            _ => app.state.warn("当前页面不是直播间"),
+        },
+        "filter" => match current_room(app) {
+            Some((roomid, _)) => {
+                let rule = match args.split_once(' ') {
+                    Some(("add", rule)) => rule.trim().to_string(),
+                    Some(("rm", rule)) => match rule.trim().strip_prefix("global ") {
+                        Some(rule) => format!("global -{}", rule.trim()),
+                        None => format!("-{}", rule.trim()),
+                    },
+                    _ => args.to_string(),
+                };
+                run_action(app, Action::EditFilter(roomid), rule);
+            },
+            None => app.state.warn("当前页面不是直播间或回放"),
+        },
+        "timeline" => run_action(app, Action::OpenTimeline, args.to_string()),
+        "replay" => run_action(app, Action::OpenReplay, args.to_string()),
+        "profile" => run_action(app, Action::SwitchProfile, args.to_string()),
+        "export" => export_room(app),
+        "theme" => {
+            let theme = match args {
+                "default" => Some(app.config.theme),
+                name => style::named_theme(name),
+            };
+            match theme {
+                Some(theme) => {
+                    style::set_theme(theme);
+                    app.state.message(format!("主题: {args}"));
+                },
+                None => app.state.warn(format!("用法: {}", spec.usage)),
+            }
+        },
+        "layout" => match view::layout::LayoutMode::parse(args) {
+            Some(mode) => {
+                app.panes.mode = mode;
+                app.state.message(format!("布局: {}", mode.name()));
+                save_layout(app);
+            },
+            None => app.state.warn(format!("用法: {}", spec.usage)),
+        },
+        _ => app.state.warn(format!("用法: {}", spec.usage)),
+    }
+    Ok(false)
+}
+
+/// 把当前页面缓存的弹幕写成录制文件, 可以用 `--replay` 打开
+fn export_room(app: &mut App) {
+    let Some(Psh::LiveRoomPageService(p)|Psh::ReplayPageService(p)) = app.state.current_page_psh() else {
+        app.state.warn("当前页面不是直播间或回放");
+        return;
+    };
+    let (roomid, events) = {
+        let page = p.watcher.borrow();
+        (page.roomid, page.danmaku_buffer.iter().map(|e| (e.ts, e.event.clone())).collect::<Vec<_>>())
+    };
+    let result = Recorder::create(roomid).and_then(|mut recorder|{
+        for (ts, event) in &events {
+            recorder.write_at(*ts, roomid, event)?;
+        }
+        Ok(recorder.path)
+    });
+    match result {
+        Ok(path) => app.state.message(format!("已导出 {} 条消息到 {}", events.len(), path.display())),
+        Err(e) => app.state.error(Error::Io(e)),
+    }
+}
+
+/// 提交输入行, 执行正在编辑的动作, 返回 `true` 表示退出
+async fn submit_input(app: &mut App) -> Result<bool, Error> {
+    use page::Action;
+    // 弹幕过长时保留输入行, 方便修改
+    if let page::InputState::EditAction { action: Action::SendDanmakuToLive(roomid), buffer, .. } = &app.state.input_state {
+        if let Some(e) = danmaku_too_long(app, *roomid, buffer.text()) {
+            app.state.error(e);
+            return Ok(false);
+        }
+    }
+    let page::InputState::EditAction { action, buffer, .. } = std::mem::take(&mut app.state.input_state) else {
+        return Ok(false);
+    };
+    let buffer = buffer.into_text();
+    app.state.push_history(&action, buffer.clone());
+    if let Action::Command = action {
+        return run_command(app, &buffer).await;
+    }
+    run_action(app, action, buffer);
+    Ok(false)
+}
+
+/// 直播间给出的弹幕字数上限(随用户等级不同), 还没取到时用配置中的值
+fn danmaku_max_length(app: &App, roomid: u64) -> usize {
+    app.state.pages.iter().find_map(|(_, psh)| match psh {
+        Psh::LiveRoomPageService(p) => {
+            let page = p.watcher.borrow();
+            (page.roomid == roomid).then(|| page.header.info.as_ref().and_then(|info| info.danmaku_max_length))
+        },
+        _ => None,
+    }).flatten().unwrap_or(app.config.danmaku_max_length)
+}
+
+fn danmaku_too_long(app: &App, roomid: u64, text: &str) -> Option<Error> {
+    let len = text.chars().count();
+    let max = danmaku_max_length(app, roomid);
+    (len > max).then_some(Error::DanmakuTooLong { len, max })
+}
+
+/// 用输入的内容执行动作, 输入行和命令面板共用
+fn run_action(app: &mut App, action: page::Action, buffer: String) {
+    use page::Action;
+    match action {
+        Action::CreatLiveRoomPage => {
+            match buffer.parse::<u64>() {
+                Ok(roomid) => {
+                    open_live_room(app, roomid);
+                },
+                Err(_) => {
+                    app.state.error(Error::InvalidRoomId(buffer))
+                },
+            }
+        },
+        Action::SendDanmakuToLive(roomid) => {
+            if buffer.trim().is_empty() {
+                return;
+            }
+            if let Some(e) = danmaku_too_long(app, roomid, &buffer) {
+                app.state.error(e);
+                return;
+            }
+            app.danmaku_queue.send(&app.webapi_service.bilibili, roomid, buffer);
+            let pending = app.danmaku_queue.pending();
+            if pending > 1 {
+                app.state.message(format!("已加入发送队列, 前面还有 {} 条", pending - 1));
+            }
+        },
+        Action::OpenReplay => {
+            open_replay(app, Path::new(buffer.trim()));
+        },
+        Action::OpenTimeline => {
+            let roomids: Result<Vec<u64>, _> = buffer.split(|c: char|c == ',' || c.is_whitespace()).filter(|s|!s.is_empty()).map(str::parse).collect();
+            match roomids {
+                Ok(roomids) if !roomids.is_empty() => open_timeline(app, roomids),
+                _ => app.state.error(Error::InvalidRoomId(buffer)),
+            }
+        },
+        Action::Silence { roomid, uid, uname } => {
+            match moderation::parse_hours(&buffer) {
+                Some(hours) => app.state.input_state = page::InputState::Confirm(Moderation::Mute { roomid, uid, uname, hours }),
+                None => app.state.warn(format!("无效的禁言时长: {buffer}")),
+            }
+        },
+        Action::Unmute(roomid) => {
+            match buffer.trim().parse() {
+                Ok(id) => app.state.input_state = page::InputState::Confirm(Moderation::Unmute { roomid, id }),
+                Err(_) => app.state.warn(format!("无效的禁言记录编号: {buffer}")),
+            }
+        },
+        Action::EditFilter(roomid) => {
+            match app.filters.apply(roomid, &buffer) {
+                Ok(message) => {
+                    app.state.message(message);
+                    if buffer.trim() != "list" {
+                        filters_changed(app);
+                    }
+                },
+                Err(e) => app.state.error(e),
+            }
+        },
+        Action::SwitchProfile => {
+            let name = buffer.trim();
+            if !profile::is_valid_name(name) {
+                app.state.error(Error::InvalidProfileName(name.to_string()));
+            } else if app.state.pages.iter().any(|(_, psh)|matches!(psh, Psh::LoginPageService(_))) {
+                app.state.warn("请先关闭登录页面再切换账号");
+            } else {
+                match app.switch_profile(name) {
+                    Ok(()) => app.state.message(format!("已切换到账号 {name}")),
+                    Err(e) => app.state.error(e),
+                }
+            }
+        },
+        Action::Command => {}
+    }
 }
 
 
 fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+    let profile = cli.profile()?;
+    let config_path = cli.config.clone().unwrap_or_else(Config::default_path);
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().thread_name("biliterm").build().map_err(Error::Io)?;
+    match cli.command {
+        Some(Command::Login) => {
+            let webapi_service = WebApiService::new(profile)?;
+            return rt.block_on(cli::login(&webapi_service));
+        }
+        Some(Command::Tail(args)) => {
+            return rt.block_on(cli::tail(args));
+        }
+        None => {}
+    }
+    let (events, rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut app = App::new(profile, &config_path, events)?;
+    app.record = cli.record;
+    install_panic_hook();
     // setup terminal
     enable_raw_mode().map_err(Error::Io)?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture).map_err(Error::Io)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste, EnableFocusChange).map_err(Error::Io)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).map_err(Error::Io)?;
     
     // terminal.draw(window)?;
-    let mut app = App::new();
-    let rt = tokio::runtime::Builder::new_current_thread().enable_all().thread_name("biliterm").build().map_err(Error::Io)?;
-    rt.block_on(run(&mut app, &mut terminal)).unwrap();
+    let result = rt.block_on(run(&mut app, &mut terminal, rx, cli.rooms, cli.replays, cli.timeline, !cli.no_restore));
 
     // restore terminal
-    disable_raw_mode().map_err(Error::Io)?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture).map_err(Error::Io)?;
-    terminal.show_cursor().map_err(Error::Io)?;
+    restore_terminal().map_err(Error::Io)?;
+    result
+}
 
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste, DisableFocusChange, cursor::Show)
+}
 
-    Ok(())
+/// panic 时先恢复终端, 否则报告会打印在备用屏幕的 raw mode 下
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+use std::{future::Future, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use tokio::sync::{mpsc, watch};
+use tui::{widgets::{Widget, Block, Borders, Paragraph}, layout::Alignment};
+
+use crate::{Evnet, error::Error};
+
+use super::{PageService, PageServiceHandle, liveroom::LiveRoomPageService};
+
+static NEXT_TICKET: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub enum ConnectingState {
+    FetchingRoomInfo,
+    Connecting,
+    Failed(String),
+}
+
+/// 直播间连接完成前的占位页面
+#[derive(Debug, Clone)]
+pub struct ConnectingPage {
+    /// 用来在连接完成时找到这个占位标签页
+    pub ticket: u64,
+    pub roomid: u64,
+    pub state: ConnectingState,
+    pub started: Instant,
+    /// 连接成功后恢复的滚动行数, 来自上次的会话
+    pub scroll: usize,
+}
+
+impl ConnectingPage {
+    pub fn is_failed(&self) -> bool {
+        matches!(self.state, ConnectingState::Failed(_))
+    }
+}
+
+impl Widget for &ConnectingPage {
+    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        let block = Block::default().borders(Borders::ALL).title(format!("直播 {}", self.roomid));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let elapsed = self.started.elapsed().as_secs();
+        let (text, style) = match &self.state {
+            ConnectingState::FetchingRoomInfo => (format!("获取房间信息中 {elapsed}s"), crate::style::theme().info),
+            ConnectingState::Connecting => (format!("连接弹幕服务器中 {elapsed}s"), crate::style::theme().info),
+            ConnectingState::Failed(reason) => (format!("连接失败: {reason}\n[r] 重试  [Ctrl-w] 关闭"), crate::style::theme().error),
+        };
+        let y = inner.top() + inner.height / 2;
+        let line = tui::layout::Rect::new(inner.left(), y, inner.width, inner.bottom().saturating_sub(y));
+        Paragraph::new(text).alignment(Alignment::Center).style(style).render(line, buf);
+    }
+}
+
+/// 等待 `f` 完成, 期间每秒刷新一次页面上的计时
+async fn with_ticks<F: Future>(f: F, tx: &watch::Sender<ConnectingPage>) -> F::Output {
+    tokio::pin!(f);
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            output = &mut f => return output,
+            _ = ticker.tick() => tx.send_modify(|_|{}),
+        }
+    }
+}
+
+/// 在后台连接直播间, 成功后通过 `Evnet::LiveRoomReady` 交给主循环替换本页面
+pub struct ConnectingPageService {
+    roomid: u64,
+    events: mpsc::UnboundedSender<Evnet>,
+    scroll: usize,
+}
+
+impl ConnectingPageService {
+    pub fn new(roomid: u64, events: mpsc::UnboundedSender<Evnet>) -> Self {
+        Self { roomid, events, scroll: 0 }
+    }
+
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+}
+
+impl PageService for ConnectingPageService {
+    type Page = ConnectingPage;
+
+    fn run(self) -> PageServiceHandle<Self::Page> {
+        let roomid = self.roomid;
+        let ticket = NEXT_TICKET.fetch_add(1, Ordering::Relaxed);
+        let page = ConnectingPage {
+            ticket,
+            roomid,
+            state: ConnectingState::FetchingRoomInfo,
+            started: Instant::now(),
+            scroll: self.scroll,
+        };
+        let (tx, watcher) = watch::channel(page);
+        let tx = Arc::new(tx);
+        let updater = tx.clone();
+        let events = self.events;
+        let task = async move {
+            let service = match with_ticks(bilive_danmaku::RoomService::new(roomid).init(), &tx).await {
+                Ok(service) => service,
+                Err(_) => {
+                    tx.send_modify(|p|p.state = ConnectingState::Failed("无法获取房间信息".into()));
+                    events.send(Evnet::Error(Error::ConnectLiveRoomFail(roomid))).unwrap_or_default();
+                    return;
+                }
+            };
+            tx.send_modify(|p|p.state = ConnectingState::Connecting);
+            match with_ticks(service.connect(), &tx).await {
+                Ok(service) => {
+                    let service = LiveRoomPageService::from_service(roomid, service);
+                    events.send(Evnet::LiveRoomReady { ticket, service }).unwrap_or_default();
+                },
+                Err(_) => {
+                    tx.send_modify(|p|p.state = ConnectingState::Failed("无法连接弹幕服务器".into()));
+                    events.send(Evnet::Error(Error::ConnectLiveRoomFail(roomid))).unwrap_or_default();
+                },
+            }
+        };
+        let handle = tokio::spawn(task);
+        PageServiceHandle {
+            watcher,
+            updater,
+            handle,
+            on_screen: Default::default(),
+        }
+    }
+}
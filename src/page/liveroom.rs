@@ -1,63 +1,756 @@
+use std::{collections::VecDeque, ops::Range, path::PathBuf, sync::Arc, time::{Duration, Instant}};
 
-use std::{collections::VecDeque};
+use bilive_danmaku::event::Event;
+use tokio::sync::{watch, broadcast};
+use tui::{widgets::{Widget, Block, Borders, Paragraph}, text::{Span, Spans}, style::Style, layout::{Rect, Layout, Direction, Constraint}};
 
-use tokio::sync::{watch};
-use tui::{widgets::{Widget, Block, Borders, Paragraph}, text::{Span, Spans}, layout::Rect};
+use crate::{view::{wrap_spans, ViewSize}, service::{record::{self, Recorder}, filter::{Filter, Verdict}, webapi::{RoomInfo, fetch_room_info}}};
 
+const ENTRY_EXPANDED_HEIGHT: u16 = 6;
+const RECONNECT_MAX_ATTEMPTS: u32 = 10;
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// 重新获取直播间资料的间隔, 开播/下播后页首随之更新
+const ROOM_INFO_INTERVAL: Duration = Duration::from_secs(300);
+pub const DEFAULT_HISTORY_SIZE: usize = 2048;
 
-#[derive(Default)]
 pub struct LiveRoomPage {
-    pub danmaku_buffer: VecDeque<bilive_danmaku::event::Event>,
+    pub danmaku_buffer: VecDeque<BufferedEvent>,
+    /// 置顶的醒目留言和到期时间(unix 毫秒), 由页面服务按各自的时钟清理
+    pub superchats: Vec<(u64, Event)>,
+    pub entry_buffer: VecDeque<Event>,
+    pub entry_expanded: bool,
     pub roomid: u64,
+    pub history_size: usize,
+    /// 向上滚动的行数(按折行后的行计), 0 表示跟随最新弹幕
+    pub scroll: usize,
+    /// 滚动期间收到的新消息数
+    pub unread: usize,
+    /// 正在写入的录制文件
+    pub recording: Option<PathBuf>,
+    /// 回放页面的播放状态, 直播页面为 `None`
+    pub replay: Option<ReplayStatus>,
+    /// 回放的录制文件
+    pub source: Option<PathBuf>,
+    pub status: ConnectionStatus,
+    /// 因处理不及时而丢弃的消息数
+    pub skipped: u64,
+    /// 选中的弹幕, 从最新一条往前数
+    pub selected: Option<usize>,
+    /// @ 到这些名字的弹幕会高亮
+    pub mentions: Vec<String>,
+    /// 全局与本房间的过滤规则, 用 [`LiveRoomPage::set_filter`] 修改
+    pub filter: Filter,
+    /// 本场出现过的用户, 以 UID 为键
+    pub user_stats: UserStatsTable,
+    /// 打开的用户信息弹窗
+    pub user_info: Option<UserInfo>,
+    /// 页首显示的直播间资料和计数
+    pub header: RoomHeader,
+    /// 弹幕区上次渲染的大小, 折行和翻页按它计算
+    pub view: ViewSize,
+}
+
+/// 弹幕区中的一条消息, 连同过滤结果
+#[derive(Debug, Clone)]
+pub struct BufferedEvent {
+    /// 收到的时间(unix 毫秒), 回放时为录制时的时间
+    pub ts: u64,
+    pub event: Event,
+    /// 收到时按当时的规则算好, 规则变化时重新计算
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RoomHeader {
+    pub info: Option<RoomInfo>,
+    pub popularity: Option<u64>,
+    pub watched: Option<u64>,
+}
+
+impl RoomHeader {
+    fn is_empty(&self) -> bool {
+        self.info.is_none() && self.popularity.is_none() && self.watched.is_none()
+    }
+
+    /// 是否显示开播时长, 只有这时才需要每分钟重绘
+    pub fn shows_uptime(&self) -> bool {
+        matches!(&self.info, Some(RoomInfo { live: true, live_start: Some(_), .. }))
+    }
+
+    fn lines(&self) -> Vec<Spans<'static>> {
+        let mut lines = Vec::with_capacity(2);
+        let mut counters = Vec::new();
+        if let Some(info) = &self.info {
+            lines.push(Spans::from(vec![
+                Span::styled(info.title.clone(), crate::style::theme().inv),
+                Span::from(format!(" {} · {}", info.anchor, info.area_name)),
+            ]));
+            counters.push(match (info.live, info.live_start) {
+                (true, Some(start)) => Span::styled(format!("● 直播中 {}", format_uptime(start)), crate::style::theme().guard),
+                (true, None) => Span::styled("● 直播中", crate::style::theme().guard),
+                (false, _) => Span::styled("○ 未开播", crate::style::theme().entry),
+            });
+            if info.online > 0 {
+                counters.push(Span::from(format!(" | 在线 {}", info.online)));
+            }
+            if let Some(rank) = info.online_rank {
+                counters.push(Span::from(format!(" | 高能榜 {rank}")));
+            }
+        }
+        if let Some(popularity) = self.popularity {
+            counters.push(Span::from(format!(" | 人气 {popularity}")));
+        }
+        if let Some(watched) = self.watched {
+            counters.push(Span::from(format!(" | {watched}人看过")));
+        }
+        lines.push(Spans::from(counters));
+        lines
+    }
+}
+
+/// 从开播到现在的时长, `时:分`
+fn format_uptime(start: u64) -> String {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(start);
+    let minutes = now.saturating_sub(start) / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Live,
+    /// 第 N 次重连
+    Reconnecting(u32),
+    Failed,
+}
+
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionStatus::Connecting => f.write_str("连接中"),
+            ConnectionStatus::Live => f.write_str("已连接"),
+            ConnectionStatus::Reconnecting(n) => write!(f, "重连中({n})"),
+            ConnectionStatus::Failed => f.write_str("连接失败"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayStatus {
+    pub speed: f64,
+    pub paused: bool,
+    pub position: usize,
+    pub total: usize,
+}
+
+impl ReplayStatus {
+    pub const MIN_SPEED: f64 = 0.25;
+    pub const MAX_SPEED: f64 = 64.0;
+
+    pub fn new(total: usize) -> Self {
+        Self {
+            speed: 1.0,
+            paused: false,
+            position: 0,
+            total,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.position >= self.total
+    }
+}
+
+impl Default for LiveRoomPage {
+    fn default() -> Self {
+        Self {
+            danmaku_buffer: VecDeque::new(),
+            superchats: Vec::new(),
+            entry_buffer: VecDeque::new(),
+            entry_expanded: false,
+            roomid: 0,
+            history_size: DEFAULT_HISTORY_SIZE,
+            scroll: 0,
+            unread: 0,
+            recording: None,
+            replay: None,
+            source: None,
+            status: ConnectionStatus::Connecting,
+            skipped: 0,
+            selected: None,
+            mentions: Vec::new(),
+            filter: Filter::default(),
+            user_stats: UserStatsTable::default(),
+            user_info: None,
+            header: RoomHeader::default(),
+            view: ViewSize::default(),
+        }
+    }
 }
 
 impl LiveRoomPage {
-    pub fn push_danmaku(&mut self, danmaku:bilive_danmaku::event::Event, ) {
+    /// `ts` 为收到事件的时间(unix 毫秒)
+    pub fn push_event(&mut self, ts: u64, event: Event) {
+        self.user_stats.record(&event);
+        match event {
+            Event::EnterRoom { .. } | Event::GuardEnterRoom { .. } | Event::Follow { .. } => {
+                self.push_entry(event)
+            },
+            Event::SuperChat { price, .. } => {
+                self.superchats.push((ts + superchat_duration(price).as_millis() as u64, event.clone()));
+                self.push_danmaku(ts, event);
+            },
+            Event::Danmaku { .. } | Event::Gift { .. } | Event::BlindboxGift { .. } | Event::GuardBuy { .. } => {
+                self.push_danmaku(ts, event)
+            },
+            Event::PopularityUpdate { popularity } => {
+                self.header.popularity = Some(popularity);
+            },
+            Event::WatchedUpdate { num } => {
+                self.header.watched = Some(num);
+            },
+            _ => {}
+        }
+    }
+
+    pub fn push_danmaku(&mut self, ts: u64, danmaku:Event) {
+        let danmaku = BufferedEvent { ts, verdict: self.filter.verdict(&danmaku), event: danmaku };
+        // 滚动时保持画面不动: 新消息占的行数都算进滚动距离
+        if self.scroll > 0 {
+            let rows = self.event_rows(&danmaku);
+            if rows > 0 {
+                self.scroll += rows;
+                self.unread += 1;
+            }
+        }
         self.danmaku_buffer.push_back(danmaku);
-        if self.danmaku_buffer.len() > 64 {
+        if let Some(selected) = &mut self.selected {
+            *selected += 1;
+        }
+        while self.danmaku_buffer.len() > self.history_size {
             self.danmaku_buffer.pop_front();
         }
+        self.selected = self.selected.filter(|s| *s < self.danmaku_buffer.len());
+    }
+
+    /// 换用新的过滤规则, 已收到的消息按新规则重新判断
+    pub fn set_filter(&mut self, filter: Filter) {
+        for entry in self.danmaku_buffer.iter_mut() {
+            entry.verdict = filter.verdict(&entry.event);
+        }
+        self.filter = filter;
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    /// 弹幕流中显示的事件: 能渲染且没有被过滤规则隐藏
+    fn is_visible(&self, entry: &BufferedEvent) -> bool {
+        entry.verdict != Verdict::Hide && event_spans(&entry.event).is_some()
+    }
+
+    /// 弹幕的正文宽度, 左边留一列
+    fn text_width(&self) -> u16 {
+        self.view.width().saturating_sub(1)
+    }
+
+    /// 事件按当前宽度折行后占的行数, 不显示的事件为 0
+    fn event_rows(&self, entry: &BufferedEvent) -> usize {
+        if entry.verdict == Verdict::Hide {
+            return 0;
+        }
+        event_spans(&entry.event).map_or(0, |spans| wrap_spans(&spans, self.text_width()).len())
+    }
+
+    /// 最多能向上滚动的行数: 最早一条到达顶端为止, 滚动时底部有一行提示
+    fn max_scroll(&self) -> usize {
+        let rows: usize = self.danmaku_buffer.iter().map(|e| self.event_rows(e)).sum();
+        rows.saturating_sub(self.view.page())
+    }
+
+    /// 可见范围内最新的一条弹幕
+    fn first_visible_offset(&self) -> usize {
+        let mut rows = 0;
+        for (offset, event) in self.danmaku_buffer.iter().rev().enumerate() {
+            rows += self.event_rows(event);
+            if rows > self.scroll {
+                return offset;
+            }
+        }
+        self.danmaku_buffer.len()
+    }
+
+    /// 选中更早的一条, 没有选中时从最新一条开始
+    pub fn select_prev(&mut self) {
+        let start = self.selected.map(|s| s + 1).unwrap_or_else(|| self.first_visible_offset());
+        let found = self.danmaku_buffer.iter().rev().enumerate().skip(start).find(|(_, e)| self.is_visible(e));
+        if let Some((offset, _)) = found {
+            self.selected = Some(offset);
+            self.scroll_to_selected();
+        }
+    }
+
+    /// 选中更新的一条, 越过最新一条时取消选中
+    pub fn select_next(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        self.selected = self.danmaku_buffer.iter().rev().enumerate().take(selected)
+            .filter(|(_, e)| self.is_visible(e))
+            .last()
+            .map(|(offset, _)| offset);
+        self.scroll_to_selected();
+    }
+
+    /// 先关闭弹窗, 再取消选中
+    pub fn clear_selection(&mut self) {
+        if self.user_info.take().is_none() {
+            self.selected = None;
+        }
+    }
+
+    /// 该用户最近的弹幕, 最早的在前
+    pub fn recent_messages(&self, uid: u64, n: usize) -> Vec<String> {
+        let mut messages: Vec<String> = self.danmaku_buffer.iter().rev()
+            .map(|e| &e.event)
+            .filter(|e| matches!(e, Event::Danmaku { user, .. } | Event::SuperChat { user, .. } if user.uid == uid))
+            .take(n)
+            .map(message_text)
+            .collect();
+        messages.reverse();
+        messages
+    }
+
+    /// 为选中的弹幕打开用户信息弹窗, 返回要获取资料的 UID
+    pub fn open_user_info(&mut self) -> Option<u64> {
+        let info = match self.selected_event()? {
+            Event::Danmaku { user, fans_medal, .. } | Event::Gift { user, fans_medal, .. } |
+            Event::BlindboxGift { user, fans_medal, .. } | Event::SuperChat { user, fans_medal, .. } => UserInfo {
+                uid: user.uid,
+                uname: user.uname.clone(),
+                fans_medal: fans_medal.clone(),
+                card: CardState::Loading,
+            },
+            other => {
+                let user = event_user(other)?;
+                UserInfo { uid: user.uid, uname: user.uname.clone(), fans_medal: None, card: CardState::Loading }
+            },
+        };
+        let uid = info.uid;
+        self.user_info = Some(info);
+        Some(uid)
+    }
+
+    pub fn selected_event(&self) -> Option<&Event> {
+        self.selected.and_then(|s| self.danmaku_buffer.iter().rev().nth(s)).map(|e| &e.event)
+    }
+
+    /// 让选中的弹幕整条留在可见范围内
+    fn scroll_to_selected(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        let mut below = 0;
+        let mut rows = 0;
+        for (offset, event) in self.danmaku_buffer.iter().rev().enumerate().take(selected + 1) {
+            if offset == selected {
+                rows = self.event_rows(event);
+            } else {
+                below += self.event_rows(event);
+            }
+        }
+        let height = self.view.page();
+        if below < self.scroll {
+            self.scroll_down(self.scroll - below);
+        } else if below + rows > self.scroll + height {
+            self.scroll = below + rows - height;
+        }
+    }
+
+    /// 弹幕中是否 @ 了当前账号
+    fn mentions_me(&self, event: &Event) -> bool {
+        match event {
+            Event::Danmaku { message, .. } => {
+                let message = message.to_string();
+                self.mentions.iter().any(|name| !name.is_empty() && message.contains(&format!("@{name}")))
+            },
+            _ => false,
+        }
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll = (self.scroll + n).min(self.max_scroll());
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        // 旧消息被挤出缓冲区后, 滚动距离可能超出了历史
+        self.scroll = self.scroll.min(self.max_scroll()).saturating_sub(n);
+        self.unread = self.unread.min(self.scroll);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.view.page())
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.view.page())
+    }
+
+    /// 回到底部, 继续跟随最新弹幕
+    pub fn follow(&mut self) {
+        self.scroll = 0;
+        self.unread = 0;
+    }
+
+    pub fn push_entry(&mut self, entry:Event) {
+        self.entry_buffer.push_back(entry);
+        if self.entry_buffer.len() > 64 {
+            self.entry_buffer.pop_front();
+        }
+    }
+
+    pub fn toggle_entries(&mut self) {
+        self.entry_expanded = !self.entry_expanded;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            replay.paused = !replay.paused;
+        }
+    }
+
+    pub fn speed_up(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            replay.speed = (replay.speed * 2.0).min(ReplayStatus::MAX_SPEED);
+        }
+    }
+
+    pub fn speed_down(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            replay.speed = (replay.speed / 2.0).max(ReplayStatus::MIN_SPEED);
+        }
+    }
+
+    fn title(&self) -> String {
+        match &self.replay {
+            Some(replay) => {
+                let state = if replay.finished() {
+                    "已结束"
+                } else if replay.paused {
+                    "暂停"
+                } else {
+                    "播放中"
+                };
+                format!("回放 {} ×{} {}/{} {state}", self.roomid, replay.speed, replay.position, replay.total)
+            },
+            None => {
+                let mut title = format!("直播 {} [{}]", self.roomid, self.status);
+                if self.skipped > 0 {
+                    title.push_str(&format!(" 跳过{}条", self.skipped));
+                }
+                if self.recording.is_some() {
+                    title.push_str(" ● REC");
+                }
+                title
+            }
+        }
+    }
+
+    /// 置顶中的醒目留言, 最早的在前
+    pub fn pinned_superchats(&self) -> impl Iterator<Item = &Event> {
+        self.superchats.iter().map(|(_, sc)| sc)
+    }
+
+    /// 下一条置顶醒目留言到期的时间(unix 毫秒)
+    pub fn next_superchat_expiry(&self) -> Option<u64> {
+        self.superchats.iter().map(|(deadline, _)| *deadline).min()
+    }
+
+    /// 清理到 `now`(unix 毫秒)为止到期的醒目留言. 直播间用当前时间, 回放用回放到的时间
+    pub fn clear_expired_superchats(&mut self, now: u64) {
+        self.superchats.retain(|(deadline, _)| *deadline > now);
     }
 }
 
+/// 醒目留言的置顶时长, 由价格(元)决定
+fn superchat_duration(price: u64) -> Duration {
+    let secs = match price {
+        0..=49 => 60,
+        50..=99 => 120,
+        100..=499 => 300,
+        500..=999 => 1800,
+        1000..=1999 => 3600,
+        _ => 7200,
+    };
+    Duration::from_secs(secs)
+}
 
-impl<'a> Widget for &'a LiveRoomPage {
-    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        let block = Block::default().borders(Borders::ALL);
-        let inner = block.inner(area);
-        let width = inner.width;
-        let top = inner.top();
-        let mut line = inner.bottom()-1;
-        let left_bound = inner.left()+1;
-        for danmaku in self.danmaku_buffer.iter().rev() {
-            match danmaku {
-                bilive_danmaku::event::Event::Danmaku { junk_flag, message, user, fans_medal } => {
-                    if *junk_flag == 2 {
-                        continue;
-                    }
-                    if line == top-1 {
-                        break;
-                    }
-                    let mut spans = Vec::with_capacity(3);
-                    let mut user_name = Span::from(user.uname.as_str());
-                    user_name.style = crate::style::INV;
-                    spans.push(user_name);
-                    if let Some(medal) = fans_medal {
-                        let mut medal = Span::from(format!("{}[{}]",medal.medal_name, medal.medal_level));
-                        medal.style = crate::style::MEDAL;
-                        spans.push(medal);
-                    }
-                    let message = Span::from(message.to_string());
-                    spans.push(message);
-                    let msg = Spans::from(spans);
-                    let p = Paragraph::new(msg);
-                    p.render(Rect::new(left_bound, line,  width, 1), buf);
-                    line -= 1;
-                },
-                _ => {}
+fn coin_name(coin_type: &str) -> &'static str {
+    match coin_type {
+        "gold" => "金瓜子",
+        _ => "银瓜子",
+    }
+}
+
+pub fn guard_name(level: u64) -> &'static str {
+    match level {
+        1 => "总督",
+        2 => "提督",
+        _ => "舰长",
+    }
+}
+
+fn user_spans<'a>(user: &'a bilive_danmaku::model::User, fans_medal: &Option<bilive_danmaku::model::FansMedal>) -> Vec<Span<'a>> {
+    let mut spans = Vec::with_capacity(3);
+    spans.push(Span::styled(user.uname.as_str(), crate::style::theme().inv));
+    if let Some(medal) = fans_medal {
+        spans.push(Span::styled(format!("{}[{}]",medal.medal_name, medal.medal_level), crate::style::theme().medal));
+    }
+    spans
+}
+
+/// 单行事件的渲染, 不在弹幕流中显示的事件返回 `None`
+/// 消息的显示内容, 关键词高亮时只把命中的部分设为高亮样式
+pub fn styled_spans<'a>(event: &'a Event, verdict: &Verdict) -> Option<Spans<'a>> {
+    let mut spans = event_spans(event)?;
+    if let Verdict::HighlightKeywords(hits) = verdict {
+        // 关键词只匹配弹幕正文, 正文是最后一段
+        if let Some(message) = spans.0.pop() {
+            spans.0.extend(split_hits(message, hits, crate::style::theme().highlight));
+        }
+    }
+    Some(spans)
+}
+
+/// 按命中范围把一段切开, 命中的部分叠加 `style`
+fn split_hits(span: Span, hits: &[Range<usize>], style: Style) -> Vec<Span<'static>> {
+    let text = span.content;
+    let mut pieces = Vec::with_capacity(hits.len() * 2 + 1);
+    let mut at = 0;
+    for hit in hits {
+        if hit.start > at {
+            pieces.push(Span::styled(text[at..hit.start].to_string(), span.style));
+        }
+        pieces.push(Span::styled(text[hit.clone()].to_string(), span.style.patch(style)));
+        at = hit.end;
+    }
+    if at < text.len() {
+        pieces.push(Span::styled(text[at..].to_string(), span.style));
+    }
+    pieces
+}
+
+pub fn event_spans(event: &Event) -> Option<Spans<'_>> {
+    match event {
+        Event::Danmaku { junk_flag, message, user, fans_medal } => {
+            if *junk_flag == 2 {
+                return None;
             }
+            let mut spans = user_spans(user, fans_medal);
+            spans.push(Span::from(message.to_string()));
+            Some(Spans::from(spans))
+        },
+        Event::Gift { user, fans_medal, gift } | Event::BlindboxGift { user, fans_medal, gift, .. } => {
+            let mut spans = user_spans(user, fans_medal);
+            spans.push(Span::styled(
+                format!(" 投喂 {}×{} ({}{})", gift.gift_name, gift.num, gift.price * gift.num, coin_name(&gift.coin_type)),
+                crate::style::theme().gift
+            ));
+            Some(Spans::from(spans))
+        },
+        Event::GuardBuy { user, level, gift_name, .. } => {
+            Some(Spans::from(vec![
+                Span::styled(user.uname.as_str(), crate::style::theme().inv),
+                Span::styled(format!(" 开通了{}({})", guard_name(*level), gift_name), crate::style::theme().guard),
+            ]))
+        },
+        Event::SuperChat { user, price, message, .. } => {
+            Some(Spans::from(vec![
+                Span::styled(user.uname.as_str(), crate::style::theme().inv),
+                Span::styled(format!(" ￥{price} {message}"), crate::style::theme().superchat),
+            ]))
+        },
+        _ => None
+    }
+}
+
+fn entry_spans(event: &Event) -> Option<Spans<'_>> {
+    match event {
+        Event::EnterRoom { user, fans_medal } => {
+            let mut spans = user_spans(user, fans_medal);
+            spans.push(Span::styled(" 进入直播间", crate::style::theme().entry));
+            Some(Spans::from(spans))
+        },
+        Event::GuardEnterRoom { user } => {
+            Some(Spans::from(vec![
+                Span::styled(user.uname.as_str(), crate::style::theme().guard),
+                Span::styled(" 进入直播间", crate::style::theme().entry),
+            ]))
+        },
+        Event::Follow { user, fans_medal } => {
+            let mut spans = user_spans(user, fans_medal);
+            spans.push(Span::styled(" 关注了直播间", crate::style::theme().entry));
+            Some(Spans::from(spans))
+        },
+        _ => None
+    }
+}
+
+/// 发出事件的用户
+pub fn event_user(event: &Event) -> Option<&bilive_danmaku::model::User> {
+    match event {
+        Event::Danmaku { user, .. } | Event::Gift { user, .. } | Event::BlindboxGift { user, .. } |
+        Event::GuardBuy { user, .. } | Event::SuperChat { user, .. } |
+        Event::EnterRoom { user, .. } | Event::GuardEnterRoom { user } | Event::Follow { user, .. } => Some(user),
+        _ => None,
+    }
+}
+
+/// 弹幕和醒目留言的正文, 其它事件为显示的文本
+pub fn message_text(event: &Event) -> String {
+    match event {
+        Event::Danmaku { message, .. } => message.to_string(),
+        Event::SuperChat { message, .. } => message.to_string(),
+        other => event_text(other),
+    }
+}
+
+/// 事件的纯文本形式, 与界面上显示的内容一致
+pub fn event_text(event: &Event) -> String {
+    match event_spans(event).or_else(|| entry_spans(event)) {
+        Some(spans) => spans.0.iter().map(|span| span.content.as_ref()).collect(),
+        None => match event {
+            Event::WatchedUpdate { num } => format!("{num}人看过"),
+            Event::PopularityUpdate { popularity } => format!("人气 {popularity}"),
+            other => format!("{other:?}"),
         }
+    }
+}
+
+impl Widget for &LiveRoomPage {
+    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        let block = Block::default().borders(Borders::ALL).title(self.title());
+        let inner = block.inner(area);
         block.render(area, buf);
+        let superchats: Vec<Vec<Spans>> = self.pinned_superchats().filter_map(|sc|superchat_lines(sc, inner.width)).collect();
+        let sc_height = (superchats.iter().map(Vec::len).sum::<usize>() as u16).min(inner.height / 3);
+        let entry_height = if self.entry_expanded { ENTRY_EXPANDED_HEIGHT } else { 1 };
+        let header = if self.header.is_empty() { Vec::new() } else { self.header.lines() };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(header.len() as u16),
+                    Constraint::Length(sc_height),
+                    Constraint::Min(1),
+                    Constraint::Length(entry_height),
+                ]
+                .as_ref(),
+            )
+            .split(inner);
+        Paragraph::new(header).render(chunks[0], buf);
+        render_superchats(&superchats, chunks[1], buf);
+        render_danmaku(self, chunks[2], buf);
+        render_entries(self, chunks[3], buf);
+        if let Some(info) = &self.user_info {
+            info.render(self, inner, buf);
+        }
+    }
+}
+
+/// 醒目留言块: 首行是用户与价格, 其后是按宽度折行的留言内容
+fn superchat_lines(sc: &Event, width: u16) -> Option<Vec<Spans<'static>>> {
+    if let Event::SuperChat { user, fans_medal, price, message, .. } = sc {
+        let mut header = user_spans(user, fans_medal);
+        header.push(Span::styled(format!(" ￥{price}"), crate::style::theme().superchat));
+        let mut lines = wrap_spans(&Spans::from(header), width);
+        lines.extend(wrap_spans(&Spans::from(Span::styled(message.as_str(), crate::style::theme().superchat)), width));
+        Some(lines)
+    } else {
+        None
+    }
+}
+
+fn render_superchats(superchats: &[Vec<Spans>], area: Rect, buf: &mut tui::buffer::Buffer) {
+    let mut line = area.top();
+    for sc in superchats.iter().rev() {
+        if line + sc.len() as u16 > area.bottom() {
+            break;
+        }
+        for row in sc {
+            Paragraph::new(row.clone()).style(crate::style::theme().superchat).render(Rect::new(area.left(), line, area.width, 1), buf);
+            line += 1;
+        }
+    }
+}
+
+fn render_danmaku(page: &LiveRoomPage, area: Rect, buf: &mut tui::buffer::Buffer) {
+    page.view.set(area);
+    if area.height == 0 {
+        return;
+    }
+    let width = page.text_width();
+    let left_bound = area.left()+1;
+    let mut line = area.bottom();
+    let mut height = area.height as usize;
+    if page.scroll > 0 {
+        line -= 1;
+        height -= 1;
+        let hint = match page.unread {
+            0 => format!("↓ 已向上滚动 {} 行 [End]回到最新", page.scroll),
+            n => format!("↓ 下方有 {n} 条新消息 [End]回到最新"),
+        };
+        Paragraph::new(hint).style(crate::style::theme().info).render(Rect::new(area.left(), line, area.width, 1), buf);
+    }
+    // 从最新一条往上逐行折好, 够跳过的滚动行数再加一屏为止
+    let mut rows = Vec::new();
+    for (offset, entry) in page.danmaku_buffer.iter().rev().enumerate() {
+        if rows.len() >= page.scroll + height {
+            break;
+        }
+        if entry.verdict == Verdict::Hide {
+            continue;
+        }
+        if let Some(spans) = styled_spans(&entry.event, &entry.verdict) {
+            let style = if page.selected == Some(offset) {
+                crate::style::theme().selected
+            } else if page.mentions_me(&entry.event) {
+                crate::style::theme().mention
+            } else if entry.verdict == Verdict::Highlight {
+                crate::style::theme().highlight
+            } else {
+                Default::default()
+            };
+            rows.extend(wrap_spans(&spans, width).into_iter().rev().map(|row| (row, style)));
+        }
+    }
+    // 历史不够一屏时停在最早的一条
+    let skip = page.scroll.min(rows.len().saturating_sub(height));
+    for (row, style) in rows.into_iter().skip(skip).take(height) {
+        line -= 1;
+        Paragraph::new(row).style(style).render(Rect::new(left_bound, line, width, 1), buf);
+    }
+}
+
+fn render_entries(page: &LiveRoomPage, area: Rect, buf: &mut tui::buffer::Buffer) {
+    if area.height == 0 {
+        return;
+    }
+    if !page.entry_expanded {
+        let mut spans = vec![Span::styled(format!("进场/关注 {} ", page.entry_buffer.len()), crate::style::theme().entry)];
+        if let Some(latest) = page.entry_buffer.back().and_then(entry_spans) {
+            spans.extend(latest.0);
+        }
+        Paragraph::new(Spans::from(spans)).render(area, buf);
+        return;
+    }
+    let mut line = area.bottom();
+    for entry in page.entry_buffer.iter().rev() {
+        if line == area.top() {
+            break;
+        }
+        if let Some(spans) = entry_spans(entry) {
+            line -= 1;
+            Paragraph::new(spans).render(Rect::new(area.left(), line, area.width, 1), buf);
+        }
     }
 }
 
@@ -66,44 +759,339 @@ use bilive_danmaku::{
     RoomService,
     Connected
 };
+use bilibili_client::Client;
+use tokio::{sync::{broadcast::error::RecvError, mpsc}, time};
 
-use super::{PageService, PageServiceHandle};
+use super::{PageService, PageServiceHandle, user_info::{UserInfo, UserStatsTable, CardState}};
+
+/// 服务器每 30 秒左右回复一次心跳(人气值), 这么久什么都没收到就认为连接已经断开.
+/// 不依赖广播通道关闭: 发送端由连接内部持有, 断线时不一定会被丢弃
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(90);
+
+pub async fn connect(roomid: u64) -> Result<RoomService<Connected>, ()> {
+    bilive_danmaku::RoomService::new(roomid).init().await.map_err(|_|())?.connect().await.map_err(|_|())
+}
+
+/// 没有期限时永远不会完成
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 第 `attempt` 次重连前的等待时间: 1s, 2s, 4s ... 最长 60s
+fn reconnect_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.saturating_sub(1).min(6)).min(RECONNECT_MAX_BACKOFF)
+}
+
+/// [`RoomConnection::next`] 的结果
+#[derive(Debug)]
+pub enum RoomUpdate {
+    Event(Event),
+    /// 因处理不及时而丢弃的消息数
+    Lagged(u64),
+    /// 连接状态有变化
+    Status(ConnectionStatus),
+}
+
+/// 与一个直播间的弹幕连接: 断线后按退避时间重连, 连续失败多次后放弃.
+/// 直播间页面, 时间线和 `tail` 共用
+pub struct RoomConnection {
+    roomid: u64,
+    /// 持有连接, 断线时先丢弃再重连
+    _service: Option<RoomService<Connected>>,
+    receiver: Option<broadcast::Receiver<Event>>,
+    last_seen: time::Instant,
+    /// 连续失败的次数
+    attempt: u32,
+    /// 下一次重连的时间, 已经放弃时为 `None`
+    retry_at: Option<time::Instant>,
+}
+
+impl RoomConnection {
+    pub fn new(roomid: u64, service: RoomService<Connected>) -> Self {
+        let mut connection = Self::from_receiver(roomid, service.subscribe());
+        connection._service = Some(service);
+        connection
+    }
+
+    /// 还没有连接, 第一次调用 [`RoomConnection::next`] 时开始连接
+    pub fn connecting(roomid: u64) -> Self {
+        Self {
+            roomid,
+            _service: None,
+            receiver: None,
+            last_seen: time::Instant::now(),
+            attempt: 0,
+            retry_at: Some(time::Instant::now()),
+        }
+    }
+
+    fn from_receiver(roomid: u64, receiver: broadcast::Receiver<Event>) -> Self {
+        Self {
+            receiver: Some(receiver),
+            ..Self::connecting(roomid)
+        }
+    }
+
+    /// 下一个事件或状态变化, 放弃重连后返回 `None`.
+    /// 可以放在 `select!` 中: 取消后再次调用会接着等同一个重连时间
+    pub async fn next(&mut self) -> Option<RoomUpdate> {
+        if let Some(receiver) = &mut self.receiver {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = time::sleep_until(self.last_seen + HEARTBEAT_TIMEOUT) => Err(RecvError::Closed),
+            };
+            return match received {
+                Ok(event) => {
+                    self.last_seen = time::Instant::now();
+                    Some(RoomUpdate::Event(event))
+                },
+                Err(RecvError::Lagged(n)) => {
+                    self.last_seen = time::Instant::now();
+                    Some(RoomUpdate::Lagged(n))
+                },
+                Err(RecvError::Closed) => {
+                    self.receiver = None;
+                    self._service = None;
+                    Some(self.schedule_retry())
+                },
+            };
+        }
+        let retry_at = self.retry_at?;
+        time::sleep_until(retry_at).await;
+        match connect(self.roomid).await {
+            Ok(service) => {
+                self.receiver = Some(service.subscribe());
+                self._service = Some(service);
+                self.last_seen = time::Instant::now();
+                self.attempt = 0;
+                self.retry_at = None;
+                Some(RoomUpdate::Status(ConnectionStatus::Live))
+            },
+            Err(()) => Some(self.schedule_retry()),
+        }
+    }
+
+    fn schedule_retry(&mut self) -> RoomUpdate {
+        self.attempt += 1;
+        if self.attempt > RECONNECT_MAX_ATTEMPTS {
+            self.retry_at = None;
+            return RoomUpdate::Status(ConnectionStatus::Failed);
+        }
+        self.retry_at = Some(time::Instant::now() + reconnect_backoff(self.attempt));
+        RoomUpdate::Status(ConnectionStatus::Reconnecting(self.attempt))
+    }
+}
 
 pub struct LiveRoomPageService {
     roomid: u64,
-    room_service: RoomService<Connected>
+    history_size: usize,
+    recorder: Option<Recorder>,
+    mentions: Vec<String>,
+    connection: RoomConnection,
+    /// 定期请主循环用当前账号获取直播间资料
+    room_info: Option<mpsc::UnboundedSender<crate::Evnet>>,
 }
+
 impl LiveRoomPageService {
-    pub async fn new(roomid: u64) -> Result<Self, ()> {
-        let service = bilive_danmaku::RoomService::new(roomid).init().await.map_err(|_|())?.connect().await.map_err(|_|())?;
-        Ok(Self {
+    pub fn from_service(roomid: u64, service: RoomService<Connected>) -> Self {
+        Self {
             roomid,
-            room_service: service
-        })
+            history_size: DEFAULT_HISTORY_SIZE,
+            recorder: None,
+            mentions: Vec::new(),
+            connection: RoomConnection::new(roomid, service),
+            room_info: None,
+        }
+    }
+
+    pub fn roomid(&self) -> u64 {
+        self.roomid
+    }
+
+    pub fn history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+
+    pub fn mentions(mut self, mentions: Vec<String>) -> Self {
+        self.mentions = mentions;
+        self
+    }
+
+    /// 把收到的每个事件录制到文件
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// 打开时和之后每隔一段时间以 [`crate::Evnet::FetchRoomInfo`] 请主循环获取直播间资料.
+    /// 不在页面里保存客户端, 切换账号后也用新账号获取
+    pub fn room_info(mut self, events: mpsc::UnboundedSender<crate::Evnet>) -> Self {
+        self.room_info = Some(events);
+        self
     }
 }
 impl PageService for LiveRoomPageService {
     type Page = LiveRoomPage;
 
     fn run(self) -> PageServiceHandle<Self::Page> {
-        let mut reciever = self.room_service.subscribe();
-        let mut live_room_page = LiveRoomPage::default();
-        live_room_page.roomid = self.roomid;
+        let live_room_page = LiveRoomPage {
+            roomid: self.roomid,
+            history_size: self.history_size,
+            recording: self.recorder.as_ref().map(|r|r.path.clone()),
+            mentions: self.mentions,
+            status: ConnectionStatus::Live,
+            ..Default::default()
+        };
         let (tx,watcher) = watch::channel(live_room_page);
+        let tx = Arc::new(tx);
+        let updater = tx.clone();
+        let roomid = self.roomid;
+        let mut recorder = self.recorder;
+        let mut connection = self.connection;
+        let room_info = self.room_info;
         let task = async move {
-            while let Ok(e) = reciever.recv().await {
-                match e {
-                    danmaku@bilive_danmaku::event::Event::Danmaku {..} => {
-                        tx.send_modify(|p|{p.push_danmaku(danmaku)});
+            // 开播时长按分钟显示, 显示时长期间没有新消息也要按时重绘
+            let mut minute = time::interval(Duration::from_secs(60));
+            minute.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            let mut refresh = time::interval(ROOM_INFO_INTERVAL);
+            refresh.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            let mut first_refresh = true;
+            loop {
+                let (expiry, shows_uptime) = {
+                    let page = tx.borrow();
+                    let expiry = page.next_superchat_expiry()
+                        .map(|t|Instant::now() + Duration::from_millis(t.saturating_sub(record::now_millis())));
+                    (expiry, page.header.shows_uptime())
+                };
+                let update = tokio::select! {
+                    update = connection.next() => update,
+                    // 醒目留言到期时也要更新页面, 否则要等到下一条消息才会消失
+                    _ = sleep_until(expiry) => {
+                        tx.send_modify(|p|p.clear_expired_superchats(record::now_millis()));
+                        continue;
+                    }
+                    _ = minute.tick(), if shows_uptime => {
+                        tx.send_modify(|_|{});
+                        continue;
+                    }
+                    _ = refresh.tick(), if room_info.is_some() => {
+                        if let Some(events) = &room_info {
+                            events.send(crate::Evnet::FetchRoomInfo { roomid, report_error: first_refresh }).unwrap_or_default();
+                        }
+                        first_refresh = false;
+                        continue;
+                    }
+                };
+                match update {
+                    Some(RoomUpdate::Event(e)) => {
+                        let ts = record::now_millis();
+                        if let Some(r) = &mut recorder {
+                            if r.write_at(ts, roomid, &e).is_err() {
+                                recorder = None;
+                                tx.send_modify(|p|p.recording = None);
+                            }
+                        }
+                        tx.send_modify(|p|{
+                            p.clear_expired_superchats(ts);
+                            p.push_event(ts, e);
+                        });
+                    },
+                    Some(RoomUpdate::Lagged(n)) => {
+                        tx.send_modify(|p|p.skipped += n);
                     },
-                    _ => {}
+                    Some(RoomUpdate::Status(status)) => {
+                        tx.send_modify(|p|p.status = status);
+                    },
+                    None => return,
                 }
             }
         };
         let handle = tokio::spawn(task);
         PageServiceHandle {
             watcher,
-            handle
+            updater,
+            handle,
+            on_screen: Default::default(),
+        }
+    }
+}
+
+/// 在后台获取直播间资料; 定期刷新时失败不提示, 等下一次
+pub fn spawn_fetch_room_info(roomid: u64, client: Arc<Client>, events: mpsc::UnboundedSender<crate::Evnet>, report_error: bool) {
+    tokio::spawn(async move {
+        match fetch_room_info(client, roomid).await {
+            Ok(info) => events.send(crate::Evnet::RoomInfo { roomid, info }),
+            Err(reason) if report_error => events.send(crate::Evnet::Message(format!("无法获取直播间 {roomid} 的资料: {reason}"))),
+            Err(_) => Ok(()),
+        }.unwrap_or_default();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn danmaku(text: &str) -> Event {
+        Event::Danmaku {
+            junk_flag: 0,
+            message: text.into(),
+            user: bilive_danmaku::model::User { uid: 1, uname: "u".into() },
+            fans_medal: None,
+        }
+    }
+
+    #[test]
+    fn keyword_highlight_styles_only_the_hits() {
+        let event = danmaku("好耶 不好");
+        let highlight = crate::style::theme().highlight;
+        let spans = styled_spans(&event, &Verdict::HighlightKeywords(vec![0..6, 10..13])).unwrap();
+        let tail: Vec<(&str, Style)> = spans.0.iter().rev().take(3).rev().map(|s| (s.content.as_ref(), s.style)).collect();
+        assert_eq!(tail, [("好耶", highlight), (" 不", Style::default()), ("好", highlight)]);
+        assert_eq!(styled_spans(&event, &Verdict::Show).unwrap().0.last().unwrap().content, "好耶 不好");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_connection_counts_as_dropped() {
+        // 发送端一直活着但没有消息, 就像连接内部还持有它而 websocket 已经断了
+        let (sender, receiver) = broadcast::channel(4);
+        let mut connection = RoomConnection::from_receiver(1, receiver);
+        sender.send(danmaku("hi")).unwrap();
+        assert!(matches!(connection.next().await, Some(RoomUpdate::Event(_))));
+        let started = time::Instant::now();
+        assert!(matches!(connection.next().await, Some(RoomUpdate::Status(ConnectionStatus::Reconnecting(1)))));
+        assert!(started.elapsed() >= HEARTBEAT_TIMEOUT);
+        assert!(connection.receiver.is_none());
+        drop(sender);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closed_channel_schedules_a_retry() {
+        let (sender, receiver) = broadcast::channel::<Event>(4);
+        let mut connection = RoomConnection::from_receiver(1, receiver);
+        drop(sender);
+        assert!(matches!(connection.next().await, Some(RoomUpdate::Status(ConnectionStatus::Reconnecting(1)))));
+        assert_eq!(connection.retry_at.map(|t| t - time::Instant::now()), Some(reconnect_backoff(1)));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut connection = RoomConnection::connecting(1);
+        for attempt in 1..=RECONNECT_MAX_ATTEMPTS {
+            assert!(matches!(connection.schedule_retry(), RoomUpdate::Status(ConnectionStatus::Reconnecting(n)) if n == attempt));
         }
+        assert!(matches!(connection.schedule_retry(), RoomUpdate::Status(ConnectionStatus::Failed)));
+        assert!(connection.retry_at.is_none());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_minute() {
+        assert_eq!(reconnect_backoff(1), Duration::from_secs(1));
+        assert_eq!(reconnect_backoff(3), Duration::from_secs(4));
+        assert_eq!(reconnect_backoff(7), Duration::from_secs(60));
+        assert_eq!(reconnect_backoff(30), Duration::from_secs(60));
     }
-}
\ No newline at end of file
+}
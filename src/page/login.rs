@@ -14,13 +14,15 @@ use super::{PageService, PageServiceHandle};
 // 
 // 
 // 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct LoginPage {
-    qrcode: Option<String>,
-    lint: String
+    pub qrcode: Option<String>,
+    pub lint: String,
+    /// 扫码登录已成功, cookie 已经写入
+    pub success: bool,
 }
 
-impl<'a> Widget for &'a LoginPage {
+impl Widget for &LoginPage {
     fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
         let block = Block::default().borders(Borders::ALL);
         let inner = block.inner(area);
@@ -41,7 +43,7 @@ impl<'a> Widget for &'a LoginPage {
             Some(code) => {
                 Paragraph::new(code.as_str()).alignment(Alignment::Center)
             },
-            None => Paragraph::new("No QrCode").alignment(Alignment::Center).style(crate::style::INFO),
+            None => Paragraph::new("No QrCode").alignment(Alignment::Center).style(crate::style::theme().info),
         };
         qrcode.render(chunks[1], buf);
     }
@@ -64,19 +66,27 @@ impl PageService for LoginPageService {
     fn run(self) -> PageServiceHandle<Self::Page> {
         let client_login_task = self.client.excute(Login{});
         let (tx, watcher) = watch::channel(LoginPage::default());
+        let tx = Arc::new(tx);
+        let updater = tx.clone();
         let task = async move {
             let mut state = client_login_task.state;
             loop {
-                state.changed().await.unwrap();
+                if state.changed().await.is_err() {
+                    tx.send_modify(|p|{p.lint = "登录任务意外结束".into()});
+                    break;
+                }
                 let state = &*state.borrow();
                 match state {
                     LoginState::FetchingQrcode => {
                         tx.send_modify(|p|{p.lint = "请求二维码中".into()})
                     },
                     LoginState::ScaningQrcode(qr) => {
-                        tx.send_modify(|p|{p.qrcode = Some(
-                            qrcode::QrCode::new(qr.as_bytes()).unwrap().render::<qrcode::render::unicode::Dense1x2>().build()
-                        )})
+                        match qrcode::QrCode::new(qr.as_bytes()) {
+                            Ok(code) => tx.send_modify(|p|{p.qrcode = Some(
+                                code.render::<qrcode::render::unicode::Dense1x2>().build()
+                            )}),
+                            Err(e) => tx.send_modify(|p|{p.lint = format!("无法生成二维码: {e}")}),
+                        }
                     },
                     LoginState::QrcodeExpired => {
                         tx.send_modify(|p|{p.lint = "二维码已过期".into()})
@@ -95,6 +105,7 @@ impl PageService for LoginPageService {
                         tx.send_modify(|p|{
                             p.lint = "登录成功".into();
                             p.qrcode = None;
+                            p.success = true;
                         });
                         break;
                     },
@@ -104,7 +115,9 @@ impl PageService for LoginPageService {
         let handle = tokio::spawn(task);
         PageServiceHandle {
             watcher,
-            handle
+            updater,
+            handle,
+            on_screen: Default::default(),
         }
     }
 }
\ No newline at end of file
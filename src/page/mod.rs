@@ -1,18 +1,28 @@
-use std::{fmt::Display};
-use tokio::sync::{watch};
+use std::{collections::{BTreeMap, HashMap}, fmt::Display, mem::Discriminant, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+use tokio::sync::{watch, mpsc};
 use tokio::task::JoinHandle;
 use tui::{widgets::Widget, Frame, backend::Backend, layout::Rect};
 
+use crate::{error::Error, view::line_editor::LineEditor, service::{moderation::Moderation, session::{Session, PageEntry, SavedPage}}};
+
 
 
 // use crate::view::PageView;
 pub mod liveroom;
 pub mod login;
+pub mod replay;
+pub mod connecting;
+pub mod timeline;
+pub mod user_info;
 use self::login::LoginPageService;
+use self::connecting::ConnectingPageService;
 use self::liveroom::LiveRoomPageService;
+use self::replay::ReplayPageService;
+use self::timeline::TimelinePageService;
 
 macro_rules! psh {
     ($($page:ident),*) => {
+        #[allow(clippy::enum_variant_names)]
         pub enum Psh {
             $($page(PageServiceHandle<<$page as PageService>::Page>),)*
         }
@@ -27,6 +37,33 @@ macro_rules! psh {
                 }
             }
 
+            /// 页面显示在屏幕上时, 状态每次变化都向主循环请求重绘, 页面关闭后自动结束
+            pub fn notify_redraw(&self, redraw: mpsc::UnboundedSender<crate::Evnet>) {
+                match self {
+                    $(Self::$page(h) => {
+                        let mut watcher = h.watcher.clone();
+                        let on_screen = h.on_screen.clone();
+                        tokio::spawn(async move {
+                            while watcher.changed().await.is_ok() {
+                                if !on_screen.load(Ordering::Relaxed) {
+                                    continue;
+                                }
+                                if redraw.send(crate::Evnet::Redraw).is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                    },)*
+                }
+            }
+
+            /// 每次渲染时由主循环更新; 切换到后台页面时本来就会重绘
+            pub fn set_on_screen(&self, on_screen: bool) {
+                match self {
+                    $(Self::$page(h) => h.on_screen.store(on_screen, Ordering::Relaxed),)*
+                }
+            }
+
             pub fn abort(self) {
                 match self {
                     $(Self::$page(h) => {
@@ -41,12 +78,49 @@ macro_rules! psh {
 
 psh!(
     LoginPageService,
-    LiveRoomPageService
+    LiveRoomPageService,
+    ReplayPageService,
+    ConnectingPageService,
+    TimelinePageService
 );
 
+impl Psh {
+    /// 页面的标识, 打开同一个直播间(或回放文件, 时间线)的页面相同, 用来记住窗格大小
+    pub fn key(&self) -> String {
+        match self {
+            Psh::LoginPageService(_) => "login".to_string(),
+            Psh::ConnectingPageService(h) => format!("live:{}", h.watcher.borrow().roomid),
+            Psh::LiveRoomPageService(h) => format!("live:{}", h.watcher.borrow().roomid),
+            Psh::ReplayPageService(h) => match &h.watcher.borrow().source {
+                Some(path) => format!("replay:{}", path.display()),
+                None => "replay".to_string(),
+            },
+            Psh::TimelinePageService(h) => {
+                let roomids: Vec<String> = h.watcher.borrow().roomids.iter().map(u64::to_string).collect();
+                format!("timeline:{}", roomids.join(","))
+            },
+        }
+    }
+
+    /// 恢复会话时还原滚动位置. 消息还没有到, 所以不按内容截断,
+    /// 页面保持在不跟随最新消息的状态, 直到回到底部
+    pub fn restore_scroll(&self, scroll: usize) {
+        match self {
+            Psh::LoginPageService(_) => {},
+            Psh::ConnectingPageService(h) => h.updater.send_modify(|p|p.scroll = scroll),
+            Psh::LiveRoomPageService(h) | Psh::ReplayPageService(h) => h.updater.send_modify(|p|p.scroll = scroll),
+            Psh::TimelinePageService(h) => h.updater.send_modify(|p|p.scroll = scroll),
+        }
+    }
+}
+
 pub struct PageServiceHandle<P> {
     pub watcher: watch::Receiver<P>,
-    pub handle: JoinHandle<()>
+    /// 供界面修改页面的视图状态(折叠, 滚动等)
+    pub updater: Arc<watch::Sender<P>>,
+    pub handle: JoinHandle<()>,
+    /// 上一帧是否显示了这个页面, 不在屏幕上的页面变化时不请求重绘
+    pub on_screen: Arc<AtomicBool>,
 }
 
 pub trait PageService:Sized 
@@ -56,17 +130,52 @@ where for <'a> &'a Self::Page: Widget
     fn run(self) -> PageServiceHandle<Self::Page>;
 }
 
+const MAX_MESSAGES: usize = 256;
+const MAX_INPUT_HISTORY: usize = 100;
+
+#[derive(Default)]
 pub struct GlobalState {
     pub pages: Vec<(String, Psh)>,
     pub current_page: Option<usize>,
-    pub messages: Vec<String>,
+    pub messages: Vec<Message>,
     pub input_state: InputState,
+    pub show_log: bool,
+    pub show_help: bool,
+    /// 页面变化时用来请求重绘
+    pub redraw: Option<mpsc::UnboundedSender<crate::Evnet>>,
+    /// 每种动作各自的输入历史, 最新的在后
+    pub input_history: HashMap<Discriminant<Action>, Vec<String>>,
+    /// 发送失败的弹幕 (房间号, 内容), 留待重发
+    pub failed_danmaku: Vec<(u64, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub level: Level,
+    pub text: String,
 }
 
 #[derive(Clone)]
 pub enum Action {
     CreatLiveRoomPage,
-    SendDanmakuToLive(u64)
+    SendDanmakuToLive(u64),
+    SwitchProfile,
+    OpenReplay,
+    EditFilter(u64),
+    OpenTimeline,
+    /// 输入禁言时长
+    Silence { roomid: u64, uid: u64, uname: String },
+    /// 输入要解除的禁言记录编号
+    Unmute(u64),
+    /// 命令面板
+    Command,
 }
 
 impl Display for Action {
@@ -78,18 +187,44 @@ impl Display for Action {
             Action::SendDanmakuToLive(_) => {
                 f.write_str("发送弹幕")
             },
+            Action::SwitchProfile => {
+                f.write_str("切换账号")
+            },
+            Action::OpenReplay => {
+                f.write_str("打开回放")
+            },
+            Action::EditFilter(_) => {
+                f.write_str("过滤规则")
+            },
+            Action::OpenTimeline => {
+                f.write_str("合并时间线 房间号")
+            },
+            Action::Silence { uname, .. } => {
+                write!(f, "禁言 {uname} 小时数(0=本场, -1=永久)")
+            },
+            Action::Unmute(_) => {
+                f.write_str("解除禁言 记录编号")
+            },
+            Action::Command => {
+                f.write_str("命令")
+            },
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum InputState {
     EditAction {
         action: Action,
-        display: String,
-        buffer: String,
+        buffer: LineEditor,
+        /// 正在浏览的历史条目, `None` 表示在编辑新内容
+        history_index: Option<usize>,
+        /// 开始浏览历史前正在编辑的内容
+        draft: String,
     },
-     
+    /// 等待确认的房管操作, `y` 执行, 其它键取消
+    Confirm(Moderation),
+    #[default]
     Normal,
 }
 
@@ -97,28 +232,89 @@ impl InputState {
     pub fn edit_action(action: Action) -> Self {
         Self::EditAction {
             action,
-            display: String::new(),
-            buffer: String::new()
+            buffer: LineEditor::default(),
+            history_index: None,
+            draft: String::new(),
         }
     }
 }
 
 
-impl Default for InputState {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 impl GlobalState {
-    pub fn current_page_psh<'a>(&'a self) -> Option<&'a Psh> {
+    pub fn current_page_psh(&self) -> Option<&Psh> {
         self.current_page.map(|idx|{
             &self.pages[idx].1
         })
     }
+    /// 各标签页的标识, 按标签页顺序
+    pub fn page_keys(&self) -> Vec<String> {
+        self.pages.iter().map(|(_, psh)| psh.key()).collect()
+    }
+
+    /// 当前页面是直播间(或回放, 时间线), 且没有在编辑输入行
+    pub fn is_normal_on_live_room(&self) -> bool {
+        matches!(self.input_state, InputState::Normal) && matches!(self.current_page_psh(), Some(Psh::LiveRoomPageService(_)|Psh::ReplayPageService(_)|Psh::TimelinePageService(_)))
+    }
+
+    /// 当前打开的页面, 用于保存会话; 登录页不保存. `weights` 为各页面的窗格权重
+    pub fn session(&self, weights: &BTreeMap<String, u16>) -> Session {
+        let mut session = Session::default();
+        for (idx, (title, psh)) in self.pages.iter().enumerate() {
+            let saved = match psh {
+                Psh::LoginPageService(_) => None,
+                Psh::ConnectingPageService(h) => {
+                    let page = h.watcher.borrow();
+                    Some((SavedPage::Live { roomid: page.roomid }, page.scroll))
+                },
+                Psh::LiveRoomPageService(h) => {
+                    let page = h.watcher.borrow();
+                    Some((SavedPage::Live { roomid: page.roomid }, page.scroll))
+                },
+                Psh::ReplayPageService(h) => {
+                    let page = h.watcher.borrow();
+                    page.source.clone().map(|path| (SavedPage::Replay {
+                        path,
+                        speed: page.replay.map(|r| r.speed).unwrap_or(1.0),
+                    }, page.scroll))
+                },
+                Psh::TimelinePageService(h) => {
+                    let page = h.watcher.borrow();
+                    Some((SavedPage::Timeline { roomids: page.roomids.clone() }, page.scroll))
+                },
+            };
+            if let Some((page, scroll)) = saved {
+                if self.current_page == Some(idx) {
+                    session.current_page = Some(session.pages.len());
+                }
+                let weight = weights.get(&psh.key()).copied();
+                session.pages.push(PageEntry { title: title.clone(), scroll, weight, page });
+            }
+        }
+        session
+    }
+
     pub fn regist_page(&mut self, title: String, psh: Psh) {
+        if let Some(redraw) = &self.redraw {
+            psh.notify_redraw(redraw.clone());
+        }
         self.pages.push((title, psh));
-        self.to_last_page();
+        self.select_last_page();
+    }
+
+    /// 替换某个标签页的页面服务, 标题与位置不变
+    pub fn replace_page(&mut self, idx: usize, psh: Psh) {
+        if let Some(redraw) = &self.redraw {
+            psh.notify_redraw(redraw.clone());
+        }
+        let old = std::mem::replace(&mut self.pages[idx].1, psh);
+        old.abort();
+    }
+
+    /// 找到指定编号的连接占位页
+    pub fn find_connecting_page(&self, ticket: u64) -> Option<usize> {
+        self.pages.iter().position(|(_, psh)|{
+            matches!(psh, Psh::ConnectingPageService(h) if h.watcher.borrow().ticket == ticket)
+        })
     }
 
     pub fn close_page(&mut self) {
@@ -133,25 +329,89 @@ impl GlobalState {
         }
     }
 
+    pub fn push_history(&mut self, action: &Action, line: String) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let history = self.input_history.entry(std::mem::discriminant(action)).or_default();
+        if history.last() != Some(&line) {
+            history.push(line);
+        }
+        if history.len() > MAX_INPUT_HISTORY {
+            history.remove(0);
+        }
+    }
+
+    /// 输入行切换到上一条历史
+    pub fn history_prev(&mut self) {
+        if let InputState::EditAction { action, buffer, history_index, draft, .. } = &mut self.input_state {
+            let Some(history) = self.input_history.get(&std::mem::discriminant(action)) else {
+                return;
+            };
+            let idx = match *history_index {
+                None if history.is_empty() => return,
+                None => {
+                    *draft = buffer.text().to_string();
+                    history.len() - 1
+                },
+                Some(idx) => idx.saturating_sub(1),
+            };
+            *history_index = Some(idx);
+            buffer.set_text(history[idx].as_str());
+        }
+    }
+
+    /// 输入行切换到下一条历史, 越过最新一条时回到草稿
+    pub fn history_next(&mut self) {
+        if let InputState::EditAction { action, buffer, history_index, draft, .. } = &mut self.input_state {
+            let Some(idx) = *history_index else {
+                return;
+            };
+            let history = self.input_history.get(&std::mem::discriminant(action)).map(Vec::as_slice).unwrap_or_default();
+            if idx + 1 < history.len() {
+                *history_index = Some(idx + 1);
+                buffer.set_text(history[idx + 1].as_str());
+            } else {
+                *history_index = None;
+                buffer.set_text(std::mem::take(draft));
+            }
+        }
+    }
+
     pub fn message(&mut self, s:impl Into<String>) {
-        self.messages.push(s.into())
+        self.push_message(Level::Info, s.into())
+    }
+
+    pub fn warn(&mut self, s:impl Into<String>) {
+        self.push_message(Level::Warn, s.into())
+    }
+
+    pub fn error(&mut self, e: Error) {
+        self.push_message(Level::Error, e.to_string())
     }
 
-    pub fn to_last_page(&mut self) {
+    fn push_message(&mut self, level: Level, text: String) {
+        self.messages.push(Message { level, text });
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    pub fn select_last_page(&mut self) {
         if !self.pages.is_empty() {
             self.current_page.replace(self.pages.len()-1);
         }
     }
 
-    // pub fn to_first_page(&mut self) {
+    // pub fn select_first_page(&mut self) {
     //     if !self.pages.is_empty() {
     //         self.current_page.replace(0);
     //     }
     // }
 
-    pub fn to_prev_page(&mut self) {
+    pub fn select_prev_page(&mut self) {
         let len = self.pages.len();
-        if self.pages.len() != 0 {
+        if !self.pages.is_empty() {
             match self.current_page {
                 Some(idx) => {
                     if idx == 0 {
@@ -167,9 +427,9 @@ impl GlobalState {
         }
     }
 
-    pub fn to_next_page(&mut self) {
+    pub fn select_next_page(&mut self) {
         let len = self.pages.len();
-        if self.pages.len() != 0 {
+        if !self.pages.is_empty() {
             match self.current_page {
                 Some(idx) => {
                     if idx == len-1 {
@@ -186,17 +446,6 @@ impl GlobalState {
     }
 }
 
-impl Default for GlobalState {
-    fn default() -> Self {
-        Self {
-            current_page: None,
-            pages: Vec::new(),
-            messages: Vec::new(),
-            input_state: InputState::default()
-        }
-    }
-}
-
 // pub struct GlobalService {
 
 // }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use tokio::{sync::watch, time::{Duration, Instant}};
+
+use crate::service::record::{self, Record};
+
+use super::{PageService, PageServiceHandle, liveroom::{LiveRoomPage, ReplayStatus, DEFAULT_HISTORY_SIZE}};
+
+/// 把录制文件按原来的时间间隔重新送进 `LiveRoomPage`
+pub struct ReplayPageService {
+    roomid: u64,
+    history_size: usize,
+    mentions: Vec<String>,
+    path: PathBuf,
+    records: Vec<Record>,
+}
+
+impl ReplayPageService {
+    /// 读取录制文件, 同时返回无法解析的行数
+    pub fn new(path: &Path) -> std::io::Result<(Self, usize)> {
+        let (records, broken) = record::read_records(path)?;
+        let roomid = records.first().map(|r|r.roomid).unwrap_or_default();
+        Ok((Self {
+            roomid,
+            history_size: DEFAULT_HISTORY_SIZE,
+            mentions: Vec::new(),
+            path: path.to_path_buf(),
+            records,
+        }, broken))
+    }
+
+    pub fn history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+
+    pub fn mentions(mut self, mentions: Vec<String>) -> Self {
+        self.mentions = mentions;
+        self
+    }
+}
+
+impl PageService for ReplayPageService {
+    type Page = LiveRoomPage;
+
+    fn run(self) -> PageServiceHandle<Self::Page> {
+        let page = LiveRoomPage {
+            roomid: self.roomid,
+            history_size: self.history_size,
+            mentions: self.mentions,
+            source: Some(self.path),
+            replay: Some(ReplayStatus::new(self.records.len())),
+            ..Default::default()
+        };
+        let (tx, watcher) = watch::channel(page);
+        let tx = Arc::new(tx);
+        let updater = tx.clone();
+        let records = self.records;
+        let mut changes = watcher.clone();
+        let task = async move {
+            let start = records.first().map(|r|r.ts).unwrap_or_default();
+            // 回放时钟(毫秒), 按倍速推进, 暂停时不动
+            let mut clock = 0.0;
+            // 醒目留言按录制时的时间到期, 比下一条消息先到期的要先推进到到期的时刻
+            let next_expiry = |tx: &watch::Sender<LiveRoomPage>| tx.borrow().next_superchat_expiry().map(|t|t.saturating_sub(start) as f64);
+            for record in records {
+                let due = record.ts.saturating_sub(start) as f64;
+                while let Some(expiry) = next_expiry(&tx).filter(|t| *t < due) {
+                    if !advance(&mut changes, &mut clock, expiry).await {
+                        return;
+                    }
+                    tx.send_modify(|p|p.clear_expired_superchats(start + clock as u64));
+                }
+                if !advance(&mut changes, &mut clock, due).await {
+                    return;
+                }
+                tx.send_modify(|p|{
+                    p.clear_expired_superchats(record.ts);
+                    p.push_event(record.ts, record.event);
+                    if let Some(replay) = &mut p.replay {
+                        replay.position += 1;
+                    }
+                });
+            }
+            // 放完之后还置顶的醒目留言也照常到期
+            while let Some(expiry) = next_expiry(&tx) {
+                if !advance(&mut changes, &mut clock, expiry).await {
+                    return;
+                }
+                tx.send_modify(|p|p.clear_expired_superchats(start + clock as u64));
+            }
+        };
+        let handle = tokio::spawn(task);
+        PageServiceHandle {
+            watcher,
+            updater,
+            handle,
+            on_screen: Default::default(),
+        }
+    }
+}
+
+/// 把回放时钟推进到 `target`(毫秒). 暂停时只等页面变化(继续或调速), 播放时睡到目标时间,
+/// 中途调速则按已过去的时间推进时钟后重新计算. 页面关闭时返回 `false`
+async fn advance(changes: &mut watch::Receiver<LiveRoomPage>, clock: &mut f64, target: f64) -> bool {
+    while *clock < target {
+        let Some(ReplayStatus { paused, speed, .. }) = changes.borrow_and_update().replay else {
+            return false;
+        };
+        if paused {
+            if changes.changed().await.is_err() {
+                return false;
+            }
+            continue;
+        }
+        let started = Instant::now();
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs_f64((target - *clock) / speed / 1000.0)) => *clock = target,
+            changed = changes.changed() => {
+                if changed.is_err() {
+                    return false;
+                }
+                *clock = (*clock + started.elapsed().as_secs_f64() * 1000.0 * speed).min(target);
+            },
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_follows_speed_and_pause() {
+        let (tx, mut changes) = watch::channel(LiveRoomPage {
+            replay: Some(ReplayStatus::new(0)),
+            ..Default::default()
+        });
+        tx.send_modify(|p|p.replay.as_mut().unwrap().speed = 2.0);
+        let mut clock = 0.0;
+        let started = Instant::now();
+        assert!(advance(&mut changes, &mut clock, 60_000.0).await);
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+        // 暂停期间时钟不走, 继续后从暂停的位置接着推进
+        tx.send_modify(|p|p.replay.as_mut().unwrap().paused = true);
+        let resume = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(100)).await;
+            tx.send_modify(|p|p.replay.as_mut().unwrap().paused = false);
+            // 页面关闭(发送端销毁)会让回放结束, 留到推进完再销毁
+            tx
+        });
+        let started = Instant::now();
+        assert!(advance(&mut changes, &mut clock, 70_000.0).await);
+        assert_eq!(started.elapsed(), Duration::from_secs(105));
+        resume.await.unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use std::{borrow::Cow, collections::VecDeque, sync::Arc};
+
+use bilive_danmaku::event::Event;
+use tokio::sync::watch;
+use tui::{widgets::{Widget, Block, Borders, Paragraph}, text::{Span, Spans}, style::{Style, Color}};
+
+use crate::{view::{wrap_spans, ViewSize}, service::filter::{Filter, Verdict}};
+
+use super::{PageService, PageServiceHandle, liveroom::{self, ConnectionStatus, RoomConnection, RoomUpdate, DEFAULT_HISTORY_SIZE}};
+
+const ROOM_COLORS: [Color; 6] = [Color::LightCyan, Color::LightGreen, Color::LightMagenta, Color::LightYellow, Color::LightBlue, Color::LightRed];
+
+/// 时间线中的一条消息
+pub struct TimelineEntry {
+    /// 房间在 `roomids` 中的位置
+    room: usize,
+    event: Event,
+    /// 不带房间标签的内容, 收到时和过滤规则变化时生成
+    spans: Spans<'static>,
+    verdict: Verdict,
+}
+
+/// 多个直播间的弹幕按到达顺序合并成一条时间线
+pub struct TimelinePage {
+    pub roomids: Vec<u64>,
+    /// 各直播间的主播名, 取到直播间资料前为 `None`
+    pub names: Vec<Option<String>>,
+    pub statuses: Vec<ConnectionStatus>,
+    /// 各直播间的过滤规则, 用 [`TimelinePage::set_filters`] 修改
+    pub filters: Vec<Filter>,
+    pub lines: VecDeque<TimelineEntry>,
+    pub history_size: usize,
+    /// 向上滚动的行数(按折行后的行计), 0 表示跟随最新弹幕
+    pub scroll: usize,
+    pub skipped: u64,
+    /// 上次渲染的大小, 折行和翻页按它计算
+    pub view: ViewSize,
+}
+
+impl TimelinePage {
+    fn push(&mut self, room: usize, event: Event) {
+        let verdict = self.filters[room].verdict(&event);
+        let Some(spans) = liveroom::styled_spans(&event, &verdict).map(into_owned) else {
+            return;
+        };
+        let entry = TimelineEntry { room, verdict, event, spans };
+        if self.scroll > 0 {
+            self.scroll += self.entry_rows(&entry);
+        }
+        self.lines.push_back(entry);
+        while self.lines.len() > self.history_size {
+            self.lines.pop_front();
+        }
+    }
+
+    /// 记下直播间的主播名, 用来标记消息
+    pub fn set_name(&mut self, roomid: u64, name: &str) {
+        for (id, slot) in self.roomids.iter().zip(self.names.iter_mut()) {
+            if *id == roomid {
+                *slot = Some(name.to_string());
+            }
+        }
+    }
+
+    /// 换用新的过滤规则, 已收到的消息按新规则重新判断
+    pub fn set_filters(&mut self, filters: Vec<Filter>) {
+        for entry in self.lines.iter_mut() {
+            entry.verdict = filters[entry.room].verdict(&entry.event);
+            if let Some(spans) = liveroom::styled_spans(&entry.event, &entry.verdict) {
+                entry.spans = into_owned(spans);
+            }
+        }
+        self.filters = filters;
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    /// 带房间标签的一条消息
+    fn tagged(&self, entry: &TimelineEntry) -> Spans<'static> {
+        let mut tagged = vec![self.label(entry.room)];
+        tagged.extend(entry.spans.0.iter().cloned());
+        Spans::from(tagged)
+    }
+
+    /// 按当前宽度折行后占的行数, 被过滤的消息为 0
+    fn entry_rows(&self, entry: &TimelineEntry) -> usize {
+        if entry.verdict == Verdict::Hide {
+            return 0;
+        }
+        wrap_spans(&self.tagged(entry), self.view.width()).len()
+    }
+
+    fn max_scroll(&self) -> usize {
+        let rows: usize = self.lines.iter().map(|e| self.entry_rows(e)).sum();
+        rows.saturating_sub(self.view.page())
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll = (self.scroll + n).min(self.max_scroll());
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll = self.scroll.min(self.max_scroll()).saturating_sub(n);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.view.page())
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.view.page())
+    }
+
+    pub fn follow(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// 主播名, 还不知道时用房间的序号
+    fn label(&self, room: usize) -> Span<'static> {
+        let style = Style::default().fg(ROOM_COLORS[room % ROOM_COLORS.len()]);
+        match &self.names[room] {
+            Some(name) => Span::styled(format!("[{name}] "), style),
+            None => Span::styled(format!("[#{}] ", room + 1), style),
+        }
+    }
+
+    fn title(&self) -> String {
+        let live = self.statuses.iter().filter(|s| **s == ConnectionStatus::Live).count();
+        let mut title = format!("时间线 {}/{} 个直播间已连接", live, self.roomids.len());
+        if self.skipped > 0 {
+            title.push_str(&format!(" 跳过{}条", self.skipped));
+        }
+        if self.scroll > 0 {
+            title.push_str(&format!(" ↑{}行", self.scroll));
+        }
+        title
+    }
+}
+
+/// 把借用事件的内容复制出来, 以便和事件一起保存
+fn into_owned(spans: Spans) -> Spans<'static> {
+    Spans::from(spans.0.into_iter().map(|span| Span::styled(Cow::Owned(span.content.into_owned()), span.style)).collect::<Vec<_>>())
+}
+
+impl Widget for &TimelinePage {
+    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        let block = Block::default().borders(Borders::ALL).title(self.title());
+        let inner = block.inner(area);
+        block.render(area, buf);
+        self.view.set(inner);
+        let height = inner.height as usize;
+        // 从最新一条往上逐行折好, 够跳过的滚动行数再加一屏为止
+        let mut rows = Vec::new();
+        for entry in self.lines.iter().rev() {
+            if rows.len() >= self.scroll + height {
+                break;
+            }
+            if entry.verdict == Verdict::Hide {
+                continue;
+            }
+            let style = match entry.verdict {
+                Verdict::Highlight => crate::style::theme().highlight,
+                _ => Style::default(),
+            };
+            rows.extend(wrap_spans(&self.tagged(entry), inner.width).into_iter().rev().map(|row| (row, style)));
+        }
+        let skip = self.scroll.min(rows.len().saturating_sub(height));
+        let mut line = inner.bottom();
+        for (row, style) in rows.into_iter().skip(skip).take(height) {
+            line -= 1;
+            Paragraph::new(row).style(style).render(tui::layout::Rect::new(inner.left(), line, inner.width, 1), buf);
+        }
+    }
+}
+
+pub struct TimelinePageService {
+    roomids: Vec<u64>,
+    history_size: usize,
+}
+
+impl TimelinePageService {
+    pub fn new(roomids: Vec<u64>) -> Self {
+        Self {
+            roomids,
+            history_size: DEFAULT_HISTORY_SIZE,
+        }
+    }
+
+    pub fn history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+}
+
+/// 连接一个直播间并把事件转发到时间线, 断线重连与直播间页面相同
+async fn follow_room(room: usize, roomid: u64, tx: Arc<watch::Sender<TimelinePage>>) {
+    let mut connection = RoomConnection::connecting(roomid);
+    while let Some(update) = connection.next().await {
+        match update {
+            RoomUpdate::Event(e) => tx.send_modify(|p| p.push(room, e)),
+            RoomUpdate::Lagged(n) => tx.send_modify(|p| p.skipped += n),
+            RoomUpdate::Status(status) => tx.send_modify(|p| p.statuses[room] = status),
+        }
+    }
+}
+
+impl PageService for TimelinePageService {
+    type Page = TimelinePage;
+
+    fn run(self) -> PageServiceHandle<Self::Page> {
+        let page = TimelinePage {
+            names: vec![None; self.roomids.len()],
+            statuses: vec![ConnectionStatus::Connecting; self.roomids.len()],
+            filters: vec![Filter::default(); self.roomids.len()],
+            roomids: self.roomids.clone(),
+            lines: VecDeque::new(),
+            history_size: self.history_size,
+            scroll: 0,
+            skipped: 0,
+            view: ViewSize::default(),
+        };
+        let (tx, watcher) = watch::channel(page);
+        let tx = Arc::new(tx);
+        let updater = tx.clone();
+        // 所有直播间在同一个任务里, 关闭页面时一起结束
+        let rooms = self.roomids.into_iter().enumerate().map(|(room, roomid)| follow_room(room, roomid, tx.clone())).collect::<Vec<_>>();
+        let handle = tokio::spawn(async move {
+            futures::future::join_all(rooms).await;
+        });
+        PageServiceHandle {
+            watcher,
+            updater,
+            handle,
+            on_screen: Default::default(),
+        }
+    }
+}
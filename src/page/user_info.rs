@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use bilive_danmaku::{event::Event, model::FansMedal};
+use tui::{widgets::{Widget, Block, Borders, Paragraph, Clear, Wrap}, text::{Span, Spans}, layout::Rect};
+
+use crate::service::webapi::UserCard;
+
+use super::liveroom::{LiveRoomPage, guard_name};
+
+const RECENT_MESSAGES: usize = 8;
+const POPUP_WIDTH: u16 = 48;
+/// 最多记录的用户数, 超出后丢弃最久没出现的四分之一
+const MAX_TRACKED_USERS: usize = 4096;
+
+/// 本场直播中收集到的用户信息
+#[derive(Debug, Clone, Default)]
+pub struct UserStats {
+    pub messages: usize,
+    /// 大航海等级, 1 总督 2 提督 3 舰长
+    pub guard_level: Option<u64>,
+    /// 最近一次出现的序号, 越大越新
+    last_seen: u64,
+}
+
+/// 以 UID 为键的用户信息, 数量有上限
+#[derive(Debug, Clone, Default)]
+pub struct UserStatsTable {
+    users: HashMap<u64, UserStats>,
+    clock: u64,
+}
+
+impl UserStatsTable {
+    pub fn get(&self, uid: u64) -> Option<&UserStats> {
+        self.users.get(&uid)
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Danmaku { user, .. } | Event::SuperChat { user, .. } => {
+                self.touch(user.uid).messages += 1;
+            },
+            Event::GuardBuy { user, level, .. } => {
+                self.touch(user.uid).guard_level = Some(*level);
+            },
+            _ => return,
+        }
+        if self.users.len() > MAX_TRACKED_USERS {
+            // 最近出现的用户序号各不相同, 保留序号最大的那些
+            let cutoff = self.clock - (MAX_TRACKED_USERS * 3 / 4) as u64;
+            self.users.retain(|_, s| s.last_seen > cutoff);
+        }
+    }
+
+    fn touch(&mut self, uid: u64) -> &mut UserStats {
+        self.clock += 1;
+        let stats = self.users.entry(uid).or_default();
+        stats.last_seen = self.clock;
+        stats
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CardState {
+    Loading,
+    Loaded(UserCard),
+    Failed(String),
+}
+
+/// 用户信息弹窗
+#[derive(Debug, Clone)]
+pub struct UserInfo {
+    pub uid: u64,
+    pub uname: String,
+    pub fans_medal: Option<FansMedal>,
+    pub card: CardState,
+}
+
+impl UserInfo {
+    fn lines(&self, page: &LiveRoomPage) -> Vec<Spans<'static>> {
+        let stats = page.user_stats.get(self.uid).cloned().unwrap_or_default();
+        let mut lines = vec![Spans::from(format!("UID: {}", self.uid))];
+        match &self.card {
+            CardState::Loading => lines.push(Spans::from("资料: 获取中…")),
+            CardState::Failed(reason) => lines.push(Spans::from(Span::styled(format!("资料获取失败: {reason}"), crate::style::theme().warn))),
+            CardState::Loaded(card) => {
+                // 弹幕中的名字是当时的, 改过名的用户显示资料中的新名字
+                if card.name != self.uname {
+                    lines.push(Spans::from(format!("现昵称: {}", card.name)));
+                }
+                lines.push(Spans::from(format!("等级: UL{}  粉丝: {}", card.level, card.follower)));
+                if !card.sign.is_empty() {
+                    lines.push(Spans::from(format!("签名: {}", card.sign)));
+                }
+            },
+        }
+        lines.push(match &self.fans_medal {
+            Some(medal) => Spans::from(vec![
+                Span::from("粉丝勋章: "),
+                Span::styled(format!("{}[{}]", medal.medal_name, medal.medal_level), crate::style::theme().medal),
+            ]),
+            None => Spans::from("粉丝勋章: 无"),
+        });
+        // 只能从本场收到的上舰消息得知, 不是用户当前的大航海身份
+        lines.push(Spans::from(match stats.guard_level {
+            Some(level) => format!("大航海(本场所见): {}", guard_name(level)),
+            None => "大航海(本场所见): 无".to_string(),
+        }));
+        lines.push(Spans::from(format!("本场弹幕: {} 条", stats.messages)));
+        for message in page.recent_messages(self.uid, RECENT_MESSAGES) {
+            lines.push(Spans::from(Span::styled(format!("· {message}"), crate::style::theme().entry)));
+        }
+        lines
+    }
+
+    /// 在 `area` 中居中绘制
+    pub fn render(&self, page: &LiveRoomPage, area: Rect, buf: &mut tui::buffer::Buffer) {
+        let lines = self.lines(page);
+        let width = POPUP_WIDTH.min(area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+        Clear.render(popup, buf);
+        let block = Block::default().borders(Borders::ALL).title(format!("{} [i/Esc] 关闭", self.uname));
+        Paragraph::new(lines).block(block).wrap(Wrap { trim: false }).render(popup, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bilive_danmaku::model::User;
+
+    use super::*;
+
+    fn danmaku(uid: u64) -> Event {
+        Event::Danmaku {
+            junk_flag: 0,
+            message: "hi".into(),
+            user: User { uid, uname: uid.to_string() },
+            fans_medal: None,
+        }
+    }
+
+    #[test]
+    fn counts_messages_per_user() {
+        let mut table = UserStatsTable::default();
+        table.record(&danmaku(1));
+        table.record(&danmaku(1));
+        table.record(&danmaku(2));
+        assert_eq!(table.get(1).map(|s| s.messages), Some(2));
+        assert_eq!(table.get(2).map(|s| s.messages), Some(1));
+        assert!(table.get(3).is_none());
+    }
+
+    #[test]
+    fn forgets_least_recently_seen_users() {
+        let mut table = UserStatsTable::default();
+        for uid in 0..MAX_TRACKED_USERS as u64 {
+            table.record(&danmaku(uid));
+        }
+        // 最早的用户刚刚又出现过, 不会被丢弃
+        table.record(&danmaku(0));
+        table.record(&danmaku(MAX_TRACKED_USERS as u64));
+        assert!(table.users.len() <= MAX_TRACKED_USERS);
+        assert!(table.get(0).is_some());
+        assert!(table.get(1).is_none());
+        assert!(table.get(MAX_TRACKED_USERS as u64).is_some());
+    }
+}
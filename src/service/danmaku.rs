@@ -0,0 +1,68 @@
+use std::{sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::Duration};
+
+use bilibili_client::{danmaku, transaction::send_danmaku_to_live::SendDanmakuToLive, Client};
+use tokio::sync::mpsc;
+
+use crate::Evnet;
+
+pub const DEFAULT_COOLDOWN_MS: u64 = 1000;
+pub const DEFAULT_MAX_LENGTH: usize = 20;
+
+struct SendRequest {
+    client: Arc<Client>,
+    roomid: u64,
+    text: String,
+}
+
+/// 弹幕发送队列: 按冷却时间依次发送, 每条的结果以 `Evnet::DanmakuSent` 报告给主循环
+pub struct DanmakuQueue {
+    cooldown: Duration,
+    events: mpsc::UnboundedSender<Evnet>,
+    sender: Option<mpsc::UnboundedSender<SendRequest>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl DanmakuQueue {
+    pub fn new(cooldown: Duration, events: mpsc::UnboundedSender<Evnet>) -> Self {
+        Self {
+            cooldown,
+            events,
+            sender: None,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// 排队等待发送的弹幕数, 包括正在发送的一条
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    pub fn send(&mut self, client: &Arc<Client>, roomid: u64, text: String) {
+        let sender = self.sender.get_or_insert_with(||{
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(run_queue(rx, self.cooldown, self.events.clone(), self.pending.clone()));
+            tx
+        });
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        sender.send(SendRequest { client: client.clone(), roomid, text }).unwrap_or_default();
+    }
+}
+
+async fn run_queue(mut rx: mpsc::UnboundedReceiver<SendRequest>, cooldown: Duration, events: mpsc::UnboundedSender<Evnet>, pending: Arc<AtomicUsize>) {
+    let mut next_send = tokio::time::Instant::now();
+    while let Some(SendRequest { client, roomid, text }) = rx.recv().await {
+        tokio::time::sleep_until(next_send).await;
+        let task = client.excute(SendDanmakuToLive {
+            roomid,
+            danmaku: danmaku!(text.as_str())
+        });
+        let result = match task.await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("{e:?}")),
+            Err(e) => Err(e.to_string()),
+        };
+        next_send = tokio::time::Instant::now() + cooldown;
+        pending.fetch_sub(1, Ordering::Relaxed);
+        events.send(Evnet::DanmakuSent { roomid, text, result }).unwrap_or_default();
+    }
+}
@@ -0,0 +1,341 @@
+use std::{collections::{BTreeMap, HashSet}, io, ops::Range, path::PathBuf};
+
+use bilive_danmaku::event::Event;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::error::Error;
+
+use super::{profile, toml_file};
+
+/// 一组过滤规则, 可以是全局的, 也可以只作用于某个直播间
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterRules {
+    pub hide_keywords: Vec<String>,
+    pub hide_patterns: Vec<String>,
+    pub hide_uids: Vec<u64>,
+    pub highlight_keywords: Vec<String>,
+    pub highlight_uids: Vec<u64>,
+    /// 低于该等级(或没有粉丝勋章)的弹幕不显示, 0 表示不限制
+    pub min_medal_level: u64,
+}
+
+/// 保存在 `filters.toml` 中的全部规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterStore {
+    pub global: FilterRules,
+    /// 以房间号为键
+    pub rooms: BTreeMap<String, FilterRules>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Show,
+    Hide,
+    /// 高亮整条, 来自用户规则
+    Highlight,
+    /// 只高亮正文中命中关键词的部分, 为正文中的字节范围, 按顺序且互不重叠
+    HighlightKeywords(Vec<Range<usize>>),
+}
+
+/// 各关键词在 `text` 中出现的位置, 重叠或相连的合并成一段
+fn keyword_hits(text: &str, keywords: &[String]) -> Vec<Range<usize>> {
+    let mut hits: Vec<Range<usize>> = keywords.iter()
+        .filter(|k| !k.is_empty())
+        .flat_map(|k| text.match_indices(k.as_str()).map(|(start, k)| start..start + k.len()))
+        .collect();
+    hits.sort_by_key(|hit| hit.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(hits.len());
+    for hit in hits {
+        match merged.last_mut() {
+            Some(last) if hit.start <= last.end => last.end = last.end.max(hit.end),
+            _ => merged.push(hit),
+        }
+    }
+    merged
+}
+
+/// 合并全局与房间规则后的过滤器, 交给页面使用
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    hide_keywords: Vec<String>,
+    hide_patterns: Vec<Regex>,
+    hide_uids: HashSet<u64>,
+    highlight_keywords: Vec<String>,
+    highlight_uids: HashSet<u64>,
+    min_medal_level: u64,
+}
+
+impl Filter {
+    pub fn verdict(&self, event: &Event) -> Verdict {
+        let (user, fans_medal, message) = match event {
+            Event::Danmaku { user, fans_medal, message, .. } => (user, fans_medal, message.to_string()),
+            Event::Gift { user, .. } | Event::BlindboxGift { user, .. } |
+            Event::GuardBuy { user, .. } | Event::SuperChat { user, .. } => {
+                // 礼物和醒目留言只按用户过滤
+                return self.user_verdict(user.uid).unwrap_or(Verdict::Show);
+            },
+            _ => return Verdict::Show,
+        };
+        if let Some(verdict) = self.user_verdict(user.uid) {
+            return verdict;
+        }
+        if self.hide_keywords.iter().any(|k| message.contains(k.as_str())) || self.hide_patterns.iter().any(|re| re.is_match(&message)) {
+            return Verdict::Hide;
+        }
+        let medal_level = fans_medal.as_ref().map_or(0, |m| m.medal_level);
+        if medal_level < self.min_medal_level {
+            return Verdict::Hide;
+        }
+        let hits = keyword_hits(&message, &self.highlight_keywords);
+        if !hits.is_empty() {
+            return Verdict::HighlightKeywords(hits);
+        }
+        Verdict::Show
+    }
+
+    fn user_verdict(&self, uid: u64) -> Option<Verdict> {
+        if self.hide_uids.contains(&uid) {
+            Some(Verdict::Hide)
+        } else if self.highlight_uids.contains(&uid) {
+            Some(Verdict::Highlight)
+        } else {
+            None
+        }
+    }
+
+    fn extend(&mut self, rules: &FilterRules) {
+        self.hide_keywords.extend(rules.hide_keywords.iter().cloned());
+        // 读取时已经检查过, 这里跳过写坏的正则
+        self.hide_patterns.extend(rules.hide_patterns.iter().filter_map(|p| Regex::new(p).ok()));
+        self.hide_uids.extend(rules.hide_uids.iter().copied());
+        self.highlight_keywords.extend(rules.highlight_keywords.iter().cloned());
+        self.highlight_uids.extend(rules.highlight_uids.iter().copied());
+        self.min_medal_level = self.min_medal_level.max(rules.min_medal_level);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleKind {
+    Hide,
+    HideRegex,
+    HideUid,
+    Highlight,
+    HighlightUid,
+    MinMedal,
+}
+
+impl RuleKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hide" => Some(Self::Hide),
+            "hide-re" => Some(Self::HideRegex),
+            "hide-uid" => Some(Self::HideUid),
+            "highlight" => Some(Self::Highlight),
+            "highlight-uid" => Some(Self::HighlightUid),
+            "min-medal" => Some(Self::MinMedal),
+            _ => None,
+        }
+    }
+}
+
+/// 过滤规则编辑命令的用法, 输入有误时显示
+pub const FILTER_USAGE: &str = "[global] [+|-]hide|hide-re|hide-uid|highlight|highlight-uid|min-medal <值>, 或 list";
+
+impl FilterStore {
+    pub fn path() -> PathBuf {
+        profile::data_dir().join("filters.toml")
+    }
+
+    /// 读取保存的规则, 文件不存在时为空; 无法使用的规则作为警告返回
+    pub fn load() -> (Self, Vec<String>) {
+        let (mut store, warning): (Self, _) = toml_file::load(&Self::path(), "过滤规则");
+        let mut warnings: Vec<String> = warning.into_iter().collect();
+        for rules in std::iter::once(&mut store.global).chain(store.rooms.values_mut()) {
+            rules.hide_patterns.retain(|p| match Regex::new(p) {
+                Ok(_) => true,
+                Err(e) => {
+                    warnings.push(format!("忽略无效的正则 {p}: {e}"));
+                    false
+                }
+            });
+        }
+        (store, warnings)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        toml_file::save(&Self::path(), self)
+    }
+
+    pub fn filter_for(&self, roomid: u64) -> Filter {
+        let mut filter = Filter::default();
+        filter.extend(&self.global);
+        if let Some(rules) = self.rooms.get(&roomid.to_string()) {
+            filter.extend(rules);
+        }
+        filter
+    }
+
+    /// `None` 为全局规则
+    pub fn rules_mut(&mut self, roomid: Option<u64>) -> &mut FilterRules {
+        match roomid {
+            Some(roomid) => self.rooms.entry(roomid.to_string()).or_default(),
+            None => &mut self.global,
+        }
+    }
+
+    /// 规则的文字描述, 用于 `list`
+    pub fn describe(&self, roomid: u64) -> String {
+        let empty = FilterRules::default();
+        let room = self.rooms.get(&roomid.to_string()).unwrap_or(&empty);
+        format!("全局: {} | 直播间 {roomid}: {}", describe_rules(&self.global), describe_rules(room))
+    }
+
+    /// 执行一条编辑命令, 返回要显示的结果. 没有 `global` 前缀时作用于 `roomid`
+    pub fn apply(&mut self, roomid: u64, command: &str) -> Result<String, Error> {
+        let invalid = || Error::InvalidFilterRule(command.to_string());
+        let mut words = command.trim();
+        let mut scope = Some(roomid);
+        if let Some(rest) = words.strip_prefix("global ") {
+            scope = None;
+            words = rest.trim_start();
+        }
+        if words == "list" {
+            return Ok(self.describe(roomid));
+        }
+        let (kind, value) = words.split_once(' ').ok_or_else(invalid)?;
+        let value = value.trim();
+        let (remove, kind) = match kind.strip_prefix('-') {
+            Some(kind) => (true, kind),
+            None => (false, kind.strip_prefix('+').unwrap_or(kind)),
+        };
+        let kind = RuleKind::parse(kind).ok_or_else(invalid)?;
+        let rules = self.rules_mut(scope);
+        match kind {
+            RuleKind::Hide => edit_list(&mut rules.hide_keywords, value.to_string(), remove),
+            RuleKind::Highlight => edit_list(&mut rules.highlight_keywords, value.to_string(), remove),
+            RuleKind::HideRegex => {
+                if !remove {
+                    Regex::new(value).map_err(|e| Error::InvalidFilterRule(format!("{value}: {e}")))?;
+                }
+                edit_list(&mut rules.hide_patterns, value.to_string(), remove)
+            },
+            RuleKind::HideUid => edit_list(&mut rules.hide_uids, value.parse().map_err(|_| invalid())?, remove),
+            RuleKind::HighlightUid => edit_list(&mut rules.highlight_uids, value.parse().map_err(|_| invalid())?, remove),
+            RuleKind::MinMedal => {
+                rules.min_medal_level = if remove { 0 } else { value.parse().map_err(|_| invalid())? };
+            },
+        }
+        let scope = match scope {
+            Some(roomid) => format!("直播间 {roomid}"),
+            None => "全局".to_string(),
+        };
+        Ok(format!("已更新{scope}过滤规则: {}", command.trim()))
+    }
+}
+
+fn edit_list<T: PartialEq>(list: &mut Vec<T>, value: T, remove: bool) {
+    if remove {
+        list.retain(|v| *v != value);
+    } else if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+fn describe_rules(rules: &FilterRules) -> String {
+    let mut parts = Vec::new();
+    if !rules.hide_keywords.is_empty() {
+        parts.push(format!("屏蔽词 {:?}", rules.hide_keywords));
+    }
+    if !rules.hide_patterns.is_empty() {
+        parts.push(format!("屏蔽正则 {:?}", rules.hide_patterns));
+    }
+    if !rules.hide_uids.is_empty() {
+        parts.push(format!("屏蔽用户 {:?}", rules.hide_uids));
+    }
+    if !rules.highlight_keywords.is_empty() {
+        parts.push(format!("高亮词 {:?}", rules.highlight_keywords));
+    }
+    if !rules.highlight_uids.is_empty() {
+        parts.push(format!("高亮用户 {:?}", rules.highlight_uids));
+    }
+    if rules.min_medal_level > 0 {
+        parts.push(format!("勋章等级 ≥{}", rules.min_medal_level));
+    }
+    if parts.is_empty() {
+        "无".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_edits_room_rules_by_default() {
+        let mut store = FilterStore::default();
+        store.apply(1, "hide 抽奖").unwrap();
+        store.apply(1, "+hide 抽奖").unwrap();
+        store.apply(1, "hide-uid 42").unwrap();
+        store.apply(1, "min-medal 5").unwrap();
+        let rules = &store.rooms["1"];
+        assert_eq!(rules.hide_keywords, ["抽奖"]);
+        assert_eq!(rules.hide_uids, [42]);
+        assert_eq!(rules.min_medal_level, 5);
+        assert_eq!(store.global, FilterRules::default());
+    }
+
+    #[test]
+    fn apply_global_and_remove() {
+        let mut store = FilterStore::default();
+        store.apply(1, "global highlight 好耶").unwrap();
+        store.apply(1, "global  highlight-uid 7").unwrap();
+        assert_eq!(store.global.highlight_keywords, ["好耶"]);
+        store.apply(1, "global -highlight 好耶").unwrap();
+        store.apply(1, "global -min-medal 0").unwrap();
+        assert!(store.global.highlight_keywords.is_empty());
+        assert_eq!(store.global.highlight_uids, [7]);
+        assert!(store.rooms.is_empty());
+    }
+
+    #[test]
+    fn apply_rejects_bad_commands() {
+        let mut store = FilterStore::default();
+        for command in ["hide", "mute 1", "hide-uid abc", "min-medal -1", "hide-re (", ""] {
+            assert!(matches!(store.apply(1, command), Err(Error::InvalidFilterRule(_))), "{command}");
+        }
+        // 删除时不检查正则
+        assert!(store.apply(1, "-hide-re (").is_ok());
+        assert!(store.apply(1, "list").unwrap().contains("直播间 1"));
+    }
+
+    #[test]
+    fn keyword_highlights_mark_only_the_hits() {
+        let mut store = FilterStore::default();
+        store.apply(1, "highlight 好耶").unwrap();
+        store.apply(1, "highlight 耶耶").unwrap();
+        let hits = keyword_hits("好耶耶 不好 好耶", &store.filter_for(1).highlight_keywords);
+        assert_eq!(hits, [0..9, 17..23]);
+        assert!(keyword_hits("好", &["".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn filter_merges_global_and_room() {
+        let mut store = FilterStore::default();
+        store.apply(1, "global hide-uid 1").unwrap();
+        store.apply(1, "global min-medal 3").unwrap();
+        store.apply(1, "hide-uid 2").unwrap();
+        store.apply(1, "min-medal 10").unwrap();
+        store.apply(2, "hide-re ^a+$").unwrap();
+        let filter = store.filter_for(1);
+        assert_eq!(filter.hide_uids, HashSet::from([1, 2]));
+        assert_eq!(filter.min_medal_level, 10);
+        assert!(filter.hide_patterns.is_empty());
+        assert_eq!(store.filter_for(2).hide_patterns.len(), 1);
+        assert_eq!(store.filter_for(3).hide_uids, HashSet::from([1]));
+    }
+}
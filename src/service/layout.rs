@@ -0,0 +1,19 @@
+use std::{io, path::PathBuf};
+
+use crate::view::layout::PaneLayout;
+
+use super::{profile, toml_file};
+
+/// 分屏设置保存在 `layout.toml` 中
+pub fn path() -> PathBuf {
+    profile::data_dir().join("layout.toml")
+}
+
+/// 读取上次的分屏设置, 文件损坏时返回默认值和警告
+pub fn load() -> (PaneLayout, Option<String>) {
+    toml_file::load(&path(), "分屏设置")
+}
+
+pub fn save(layout: &PaneLayout) -> io::Result<()> {
+    toml_file::save(&path(), layout)
+}
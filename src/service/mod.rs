@@ -1 +1,9 @@
-pub mod webapi;
\ No newline at end of file
+pub mod danmaku;
+pub mod filter;
+pub mod layout;
+pub mod moderation;
+pub mod profile;
+pub mod record;
+pub mod session;
+pub mod toml_file;
+pub mod webapi;
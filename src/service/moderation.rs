@@ -0,0 +1,113 @@
+use std::{fmt::Display, sync::Arc};
+
+use bilibili_client::{transaction::live_silent_user::{AddSilentUser, DelSilentUser, GetSilentUserList}, Client};
+use tokio::sync::mpsc;
+
+use crate::{error::Error, Evnet};
+
+/// 房管操作, 执行前需要在输入行确认
+#[derive(Debug, Clone)]
+pub enum Moderation {
+    /// 禁言, `hours` 为 0 表示到本场直播结束, -1 表示永久
+    Mute { roomid: u64, uid: u64, uname: String, hours: i64 },
+    /// 解除禁言, `id` 为禁言列表中的记录编号
+    Unmute { roomid: u64, id: u64 },
+    ListMuted { roomid: u64 },
+}
+
+impl Display for Moderation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Moderation::Mute { roomid, uname, hours: -1, .. } => write!(f, "在直播间 {roomid} 永久禁言 {uname}"),
+            Moderation::Mute { roomid, uname, hours: 0, .. } => write!(f, "在直播间 {roomid} 禁言 {uname} 到本场结束"),
+            Moderation::Mute { roomid, uname, hours, .. } => write!(f, "在直播间 {roomid} 禁言 {uname} {hours} 小时"),
+            Moderation::Unmute { roomid, id } => write!(f, "在直播间 {roomid} 解除禁言记录 {id}"),
+            Moderation::ListMuted { roomid } => write!(f, "查看直播间 {roomid} 的禁言列表"),
+        }
+    }
+}
+
+/// 禁言时长的输入: 小时数, `本场`/`0`, `永久`/`-1`
+pub fn parse_hours(s: &str) -> Option<i64> {
+    match s.trim() {
+        "本场" => Some(0),
+        "永久" => Some(-1),
+        s => s.parse().ok().filter(|h| *h >= -1 && *h <= 720),
+    }
+}
+
+impl Moderation {
+    /// 在后台执行, 结果以消息或错误报告给主循环
+    pub fn spawn(self, client: Arc<Client>, events: mpsc::UnboundedSender<Evnet>) {
+        tokio::spawn(async move {
+            let description = self.to_string();
+            match self.execute(&client).await {
+                Ok(lines) => {
+                    events.send(Evnet::Message(format!("{description}: 成功"))).unwrap_or_default();
+                    for line in lines {
+                        events.send(Evnet::Message(line)).unwrap_or_default();
+                    }
+                },
+                Err(reason) => events.send(Evnet::Error(Error::ModerationFail { action: description, reason })).unwrap_or_default(),
+            }
+        });
+    }
+
+    async fn execute(&self, client: &Arc<Client>) -> Result<Vec<String>, String> {
+        match self {
+            Moderation::Mute { roomid, uid, hours, .. } => {
+                let task = client.excute(AddSilentUser { roomid: *roomid, tuid: *uid, hour: *hours });
+                flatten(task.await).map(|_| Vec::new())
+            },
+            Moderation::Unmute { roomid, id } => {
+                let task = client.excute(DelSilentUser { roomid: *roomid, id: *id });
+                flatten(task.await).map(|_| Vec::new())
+            },
+            Moderation::ListMuted { roomid } => {
+                let task = client.excute(GetSilentUserList { roomid: *roomid, page: 1 });
+                let list = flatten(task.await)?;
+                if list.is_empty() {
+                    return Ok(vec!["禁言列表为空".to_string()]);
+                }
+                Ok(list.into_iter().map(|u| format!("[{}] {}({}) 至 {}", u.id, u.tname, u.tuid, u.block_end_time)).collect())
+            },
+        }
+    }
+}
+
+/// 把后台请求的两层结果合成一个, 错误转成可显示的文字
+fn flatten<T, E: std::fmt::Debug>(result: Result<Result<T, E>, tokio::task::JoinError>) -> Result<T, String> {
+    match result {
+        Ok(Ok(t)) => Ok(t),
+        Ok(Err(e)) => Err(format!("{e:?}")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hours_accepts_words_and_range() {
+        assert_eq!(parse_hours("本场"), Some(0));
+        assert_eq!(parse_hours(" 永久 "), Some(-1));
+        assert_eq!(parse_hours("0"), Some(0));
+        assert_eq!(parse_hours("-1"), Some(-1));
+        assert_eq!(parse_hours("24"), Some(24));
+        assert_eq!(parse_hours("720"), Some(720));
+    }
+
+    #[test]
+    fn parse_hours_rejects_the_rest() {
+        for s in ["", "-2", "721", "1.5", "一天"] {
+            assert_eq!(parse_hours(s), None, "{s}");
+        }
+    }
+
+    #[test]
+    fn permanent_mute_is_described_as_such() {
+        let mute = Moderation::Mute { roomid: 1, uid: 2, uname: "u".into(), hours: -1 };
+        assert_eq!(mute.to_string(), "在直播间 1 永久禁言 u");
+    }
+}
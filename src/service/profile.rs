@@ -0,0 +1,89 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+pub const DEFAULT_PROFILE: &str = "default";
+const APP_NAME: &str = "biliterm";
+/// 旧版本保存在当前目录的 cookie 文件
+const LEGACY_COOKIE_FILE: &str = "webapi.cookie";
+
+/// `$XDG_CONFIG_HOME/biliterm`, 缺省为 `~/.config/biliterm`
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME/biliterm`, 缺省为 `~/.local/share/biliterm`
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    let base = std::env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join(APP_NAME)
+}
+
+/// 一个账号档案, 每个档案有独立的 cookie 文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+}
+
+impl Profile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        data_dir().join("profiles").join(&self.name)
+    }
+
+    /// 确保档案目录存在, 并返回其中的 cookie 文件路径.
+    /// 默认档案还没有 cookie 时导入旧版本留在当前目录的文件, 升级后不用重新登录
+    pub fn cookie_file(&self) -> io::Result<PathBuf> {
+        let dir = self.dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("webapi.cookie");
+        if self.name == DEFAULT_PROFILE && !path.exists() && Path::new(LEGACY_COOKIE_FILE).is_file() {
+            fs::copy(LEGACY_COOKIE_FILE, &path)?;
+        }
+        Ok(path)
+    }
+
+    /// 上次使用的档案, 没有记录时为 `default`
+    pub fn last_active() -> Self {
+        fs::read_to_string(data_dir().join("active_profile"))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| is_valid_name(name))
+            .map(Self::new)
+            .unwrap_or_else(|| Self::new(DEFAULT_PROFILE))
+    }
+
+    pub fn set_active(&self) -> io::Result<()> {
+        let dir = data_dir();
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("active_profile"), &self.name)
+    }
+
+    /// 所有已存在的档案名, 按字母序
+    pub fn list() -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(data_dir().join("profiles"))
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|e| e.path().is_dir())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+}
+
+/// 档案名会用作目录名, 只允许字母数字与 `-` `_`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
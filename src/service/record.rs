@@ -0,0 +1,79 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufRead, BufReader, BufWriter, Write}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use bilive_danmaku::event::Event;
+use serde::{Deserialize, Serialize};
+
+use super::profile;
+
+/// 录制文件中的一行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// unix 毫秒时间戳
+    pub ts: u64,
+    pub roomid: u64,
+    pub event: Event,
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d|d.as_millis() as u64).unwrap_or_default()
+}
+
+pub fn records_dir() -> PathBuf {
+    profile::data_dir().join("records")
+}
+
+/// 把直播间事件以 JSONL 形式追加写入文件
+pub struct Recorder {
+    pub path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    /// 在录制目录下新建 `<roomid>-<unix秒>.jsonl`
+    pub fn create(roomid: u64) -> io::Result<Self> {
+        let dir = records_dir();
+        fs::create_dir_all(&dir)?;
+        Self::open(dir.join(format!("{roomid}-{}.jsonl", now_millis() / 1000)))
+    }
+
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// 写入一条事件, `ts` 为收到它的时间(unix 毫秒)
+    pub fn write_at(&mut self, ts: u64, roomid: u64, event: &Event) -> io::Result<()> {
+        let record = RecordRef { ts, roomid, event };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+#[derive(Serialize)]
+struct RecordRef<'a> {
+    ts: u64,
+    roomid: u64,
+    event: &'a Event,
+}
+
+/// 读取录制文件, 返回可解析的记录与无法解析的行数
+pub fn read_records(path: &Path) -> io::Result<(Vec<Record>, usize)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    let mut broken = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(&line) {
+            Ok(record) => records.push(record),
+            Err(_) => broken += 1,
+        }
+    }
+    Ok((records, broken))
+}
@@ -0,0 +1,84 @@
+use std::{io, path::PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use super::{profile, toml_file};
+
+/// 会话中的一个标签页
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageEntry {
+    pub title: String,
+    /// 向上滚动的行数, 0 表示跟随最新消息
+    #[serde(default)]
+    pub scroll: usize,
+    /// 分屏时的窗格权重, 没有调整过时为 `None`
+    #[serde(default)]
+    pub weight: Option<u16>,
+    #[serde(flatten)]
+    pub page: SavedPage,
+}
+
+/// 恢复标签页所需的内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SavedPage {
+    Live { roomid: u64 },
+    Replay { path: PathBuf, speed: f64 },
+    Timeline { roomids: Vec<u64> },
+}
+
+/// 退出时打开着的标签页, 保存在 `session.toml` 中, 下次启动时恢复.
+/// 过滤规则和分屏方式各自保存, 不在这里
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    // toml 要求普通值写在表之前
+    pub current_page: Option<usize>,
+    pub pages: Vec<PageEntry>,
+}
+
+impl Session {
+    pub fn path() -> PathBuf {
+        profile::data_dir().join("session.toml")
+    }
+
+    /// 读取上次的会话, 文件损坏时返回空会话和警告
+    pub fn load() -> (Self, Option<String>) {
+        toml_file::load(&Self::path(), "会话文件")
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        toml_file::save(&Self::path(), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let session = Session {
+            current_page: Some(1),
+            pages: vec![
+                PageEntry { title: "直播1".into(), scroll: 0, weight: None, page: SavedPage::Live { roomid: 1 } },
+                PageEntry { title: "回放".into(), scroll: 12, weight: Some(3), page: SavedPage::Replay { path: "/tmp/a.jsonl".into(), speed: 2.0 } },
+                PageEntry { title: "时间线2".into(), scroll: 0, weight: Some(1), page: SavedPage::Timeline { roomids: vec![1, 2] } },
+            ],
+        };
+        let text = toml::to_string_pretty(&session).unwrap();
+        assert_eq!(toml::from_str::<Session>(&text).unwrap(), session);
+    }
+
+    #[test]
+    fn old_entries_without_scroll_or_weight() {
+        let session: Session = toml::from_str(r#"
+            current_page = 0
+            [[pages]]
+            kind = "live"
+            roomid = 7
+            title = "直播7"
+        "#).unwrap();
+        assert_eq!(session.pages, [PageEntry { title: "直播7".into(), scroll: 0, weight: None, page: SavedPage::Live { roomid: 7 } }]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs, io, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 读取 toml 文件, 文件不存在时为默认值; 无法读取或格式错误时返回默认值和警告.
+/// `what` 是文件的名称, 用于警告
+pub fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> (T, Option<String>) {
+    match fs::read_to_string(path) {
+        Ok(text) => match toml::from_str(&text) {
+            Ok(value) => (value, None),
+            Err(e) => (T::default(), Some(format!("{what} {} 格式错误: {e}", path.display()))),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (T::default(), None),
+        Err(e) => (T::default(), Some(format!("无法读取{what} {}: {e}", path.display()))),
+    }
+}
+
+/// 写入 toml 文件, 需要时创建所在目录
+pub fn save<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = toml::to_string_pretty(value).map_err(io::Error::other)?;
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn missing_file_is_default_and_broken_file_warns() {
+        let dir = std::env::temp_dir().join(format!("biliterm-toml-file-{}", std::process::id()));
+        let path = dir.join("a.toml");
+        let (value, warning): (BTreeMap<String, u16>, _) = load(&path, "测试文件");
+        assert!(value.is_empty() && warning.is_none());
+        let value = BTreeMap::from([("a".to_string(), 1u16)]);
+        save(&path, &value).unwrap();
+        assert_eq!(load(&path, "测试文件"), (value, None));
+        fs::write(&path, "a = [").unwrap();
+        let (value, warning): (BTreeMap<String, u16>, _) = load(&path, "测试文件");
+        assert!(value.is_empty());
+        assert!(warning.unwrap().starts_with("测试文件 "));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,23 +1,106 @@
-
 use std::sync::Arc;
-use bilibili_client::{Client, ClientConfig};
+use bilibili_client::{transaction::{user_card::GetUserCard, live_room_info::GetLiveRoomInfo, live_user_info::GetLiveUserInfo, live_online_rank::GetOnlineRankCount, nav::GetNav}, Client, ClientConfig};
+
+use super::profile::Profile;
 
 pub struct WebApiService {
     pub bilibili: Arc<Client>,
+    pub profile: Profile,
+}
+
+/// 用户的公开资料
+#[derive(Debug, Clone)]
+pub struct UserCard {
+    pub name: String,
+    pub level: u64,
+    pub sign: String,
+    pub follower: u64,
+}
+
+/// 直播间资料
+#[derive(Debug, Clone)]
+pub struct RoomInfo {
+    pub title: String,
+    pub area_name: String,
+    pub anchor: String,
+    pub live: bool,
+    /// 开播时间(unix 秒), 未开播为 `None`
+    pub live_start: Option<u64>,
+    pub online: u64,
+    /// 高能榜人数, 获取失败时为 `None`
+    pub online_rank: Option<u64>,
+    /// 当前账号在这个直播间的弹幕字数上限, 获取失败时为 `None`
+    pub danmaku_max_length: Option<usize>,
 }
 
 use crate::error::Error;
 impl WebApiService {
-    pub fn new() -> Result<Self, Error> {
-        use std::path::Path;
+    pub fn new(profile: Profile) -> Result<Self, Error> {
+        let cookie_file = profile.cookie_file().map_err(Error::Io)?;
         let config = ClientConfig {
-            cookie_file: Some(Path::new("./webapi.cookie")),
+            cookie_file: Some(cookie_file.as_path()),
         };
         let client = Client::new(config).map_err(Error::WebApiClientFail)?;
 
 
         Ok(Self {
             bilibili: client,
+            profile,
         })
     }
-}
\ No newline at end of file
+}
+
+/// 获取用户资料, 错误转成可显示的文字
+pub async fn fetch_user_card(client: Arc<Client>, uid: u64) -> Result<UserCard, String> {
+    let task = client.excute(GetUserCard { mid: uid });
+    match task.await {
+        Ok(Ok(card)) => Ok(UserCard {
+            name: card.name,
+            level: card.level,
+            sign: card.sign,
+            follower: card.follower,
+        }),
+        Ok(Err(e)) => Err(format!("{e:?}")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 获取当前账号的昵称, 未登录时为 `None`
+pub async fn fetch_account_name(client: Arc<Client>) -> Result<Option<String>, String> {
+    let task = client.excute(GetNav {});
+    match task.await {
+        Ok(Ok(nav)) => Ok(nav.is_login.then_some(nav.uname)),
+        Ok(Err(e)) => Err(format!("{e:?}")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 获取直播间资料, 错误转成可显示的文字
+pub async fn fetch_room_info(client: Arc<Client>, roomid: u64) -> Result<RoomInfo, String> {
+    let task = client.excute(GetLiveRoomInfo { roomid });
+    let limit = client.excute(GetLiveUserInfo { roomid });
+    let rank = client.excute(GetOnlineRankCount { roomid });
+    // 字数上限和高能榜取不到不影响其他资料
+    let danmaku_max_length = match limit.await {
+        Ok(Ok(user)) => Some(user.danmu_length as usize).filter(|n| *n > 0),
+        _ => None,
+    };
+    let online_rank = match rank.await {
+        Ok(Ok(rank)) => Some(rank.count),
+        _ => None,
+    };
+    match task.await {
+        Ok(Ok(info)) => Ok(RoomInfo {
+            title: info.title,
+            area_name: info.area_name,
+            anchor: info.uname,
+            live: info.live_status == 1,
+            live_start: Some(info.live_time).filter(|t| *t > 0),
+            online: info.online,
+            online_rank,
+            danmaku_max_length,
+        }),
+        Ok(Err(e)) => Err(format!("{e:?}")),
+        Err(e) => Err(e.to_string()),
+    }
+}
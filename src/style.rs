@@ -1,3 +1,5 @@
+use std::sync::RwLock;
+
 use tui::{style::{Style, Color, Modifier}};
 
 
@@ -48,4 +50,184 @@ pub const MEDAL: Style = Style {
     bg: Some(Color::Blue),
     add_modifier: Modifier::empty(),
     sub_modifier: Modifier::empty()
-};
\ No newline at end of file
+};
+
+pub const GIFT: Style = Style {
+    fg: Some(Color::LightYellow),
+    bg: None,
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty()
+};
+
+pub const GUARD: Style = Style {
+    fg: Some(Color::LightMagenta),
+    bg: None,
+    add_modifier: Modifier::BOLD,
+    sub_modifier: Modifier::empty()
+};
+
+pub const SUPERCHAT: Style = Style {
+    fg: Some(Color::Black),
+    bg: Some(Color::LightYellow),
+    add_modifier: Modifier::BOLD,
+    sub_modifier: Modifier::empty()
+};
+
+pub const ENTRY: Style = Style {
+    fg: Some(Color::DarkGray),
+    bg: None,
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty()
+};
+
+pub const MENTION: Style = Style {
+    fg: Some(Color::Black),
+    bg: Some(Color::LightCyan),
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty()
+};
+
+pub const HIGHLIGHT: Style = Style {
+    fg: Some(Color::Black),
+    bg: Some(Color::LightGreen),
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty()
+};
+
+pub const FOCUS: Style = Style {
+    fg: Some(Color::LightCyan),
+    bg: None,
+    add_modifier: Modifier::BOLD,
+    sub_modifier: Modifier::empty()
+};
+
+pub const SELECTED: Style = Style {
+    fg: None,
+    bg: None,
+    add_modifier: Modifier::REVERSED,
+    sub_modifier: Modifier::empty()
+};
+
+/// 运行时使用的样式表, 默认值为上面的常量, 可由配置文件覆盖
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub critical: Style,
+    pub error: Style,
+    pub warn: Style,
+    pub info: Style,
+    pub debug: Style,
+    pub inv: Style,
+    pub medal: Style,
+    pub gift: Style,
+    pub guard: Style,
+    pub superchat: Style,
+    pub entry: Style,
+    pub mention: Style,
+    pub highlight: Style,
+    pub selected: Style,
+    pub focus: Style,
+}
+
+pub const DEFAULT_THEME: Theme = Theme {
+    critical: CRITICAL,
+    error: ERROR,
+    warn: WARN,
+    info: INFO,
+    debug: DEBUG,
+    inv: INV,
+    medal: MEDAL,
+    gift: GIFT,
+    guard: GUARD,
+    superchat: SUPERCHAT,
+    entry: ENTRY,
+    mention: MENTION,
+    highlight: HIGHLIGHT,
+    selected: SELECTED,
+    focus: FOCUS,
+};
+
+/// 浅色背景的终端
+pub const LIGHT_THEME: Theme = Theme {
+    debug: Style { fg: Some(Color::Black), bg: Some(Color::Gray), ..DEBUG },
+    inv: Style { fg: Some(Color::White), bg: Some(Color::Black), ..INV },
+    gift: Style { fg: Some(Color::Yellow), ..GIFT },
+    guard: Style { fg: Some(Color::Magenta), ..GUARD },
+    entry: Style { fg: Some(Color::Gray), ..ENTRY },
+    focus: Style { fg: Some(Color::Blue), ..FOCUS },
+    ..DEFAULT_THEME
+};
+
+const PLAIN: Style = Style {
+    fg: None,
+    bg: None,
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty()
+};
+
+/// 不使用颜色, 只用粗体, 反色等区分
+pub const MONO_THEME: Theme = Theme {
+    critical: Style { add_modifier: Modifier::BOLD.union(Modifier::REVERSED), ..PLAIN },
+    error: Style { add_modifier: Modifier::BOLD.union(Modifier::REVERSED), ..PLAIN },
+    warn: Style { add_modifier: Modifier::REVERSED, ..PLAIN },
+    info: Style { add_modifier: Modifier::BOLD, ..PLAIN },
+    debug: PLAIN,
+    inv: Style { add_modifier: Modifier::REVERSED, ..PLAIN },
+    medal: Style { add_modifier: Modifier::UNDERLINED, ..PLAIN },
+    gift: Style { add_modifier: Modifier::ITALIC, ..PLAIN },
+    guard: Style { add_modifier: Modifier::BOLD, ..PLAIN },
+    superchat: Style { add_modifier: Modifier::BOLD.union(Modifier::UNDERLINED), ..PLAIN },
+    entry: Style { add_modifier: Modifier::DIM, ..PLAIN },
+    mention: Style { add_modifier: Modifier::BOLD.union(Modifier::UNDERLINED), ..PLAIN },
+    highlight: Style { add_modifier: Modifier::BOLD, ..PLAIN },
+    selected: SELECTED,
+    focus: Style { add_modifier: Modifier::BOLD, ..PLAIN },
+};
+
+static THEME: RwLock<Theme> = RwLock::new(DEFAULT_THEME);
+
+impl Default for Theme {
+    fn default() -> Self {
+        DEFAULT_THEME
+    }
+}
+
+impl Theme {
+    pub fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        match name {
+            "critical" => Some(&mut self.critical),
+            "error" => Some(&mut self.error),
+            "warn" => Some(&mut self.warn),
+            "info" => Some(&mut self.info),
+            "debug" => Some(&mut self.debug),
+            "inv" => Some(&mut self.inv),
+            "medal" => Some(&mut self.medal),
+            "gift" => Some(&mut self.gift),
+            "guard" => Some(&mut self.guard),
+            "superchat" => Some(&mut self.superchat),
+            "entry" => Some(&mut self.entry),
+            "mention" => Some(&mut self.mention),
+            "highlight" => Some(&mut self.highlight),
+            "selected" => Some(&mut self.selected),
+            "focus" => Some(&mut self.focus),
+            _ => None,
+        }
+    }
+}
+
+/// 内置主题, `default` 由调用方按配置文件处理
+pub fn named_theme(name: &str) -> Option<Theme> {
+    match name {
+        "dark" => Some(DEFAULT_THEME),
+        "light" => Some(LIGHT_THEME),
+        "mono" => Some(MONO_THEME),
+        _ => None,
+    }
+}
+
+pub fn theme() -> Theme {
+    *THEME.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_theme(theme: Theme) {
+    *THEME.write().unwrap_or_else(|e| e.into_inner()) = theme;
+}
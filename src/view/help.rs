@@ -0,0 +1,71 @@
+use tui::{widgets::{Widget, Block, Borders, Paragraph, Clear}, text::{Span, Spans}, layout::{Rect, Layout, Direction, Constraint}};
+
+use crate::{config::{Binding, KeyChord, KeyMap}, view::line_editor::{EditKey, EDIT_KEYS}};
+
+/// 某个操作的全部按键, 用 `/` 连接
+pub fn chords_text(keys: &KeyMap, binding: Binding) -> String {
+    join_chords(keys.chords(binding))
+}
+
+fn join_chords<'a>(chords: impl Iterator<Item = &'a KeyChord>) -> String {
+    chords.map(|c| c.to_string()).collect::<Vec<_>>().join("/")
+}
+
+fn hint(chord: &KeyChord, description: &str) -> [Span<'static>; 2] {
+    [
+        Span::styled(format!(" {chord} "), crate::style::theme().inv),
+        Span::from(format!(" {description}  ")),
+    ]
+}
+
+/// 提示行: 每个操作显示第一个按键和说明, 没有按键的操作不显示
+pub fn hint_line(keys: &KeyMap, bindings: &[Binding]) -> Spans<'static> {
+    let spans = bindings.iter().filter_map(|binding| Some(hint(keys.chords(*binding).next()?, binding.description())));
+    Spans::from(spans.flatten().collect::<Vec<_>>())
+}
+
+/// 编辑输入行时的提示行
+pub fn edit_hint_line(edit_keys: &[EditKey]) -> Spans<'static> {
+    let spans = edit_keys.iter().filter_map(|edit_key| Some(hint(edit_key.chords().first()?, edit_key.description())));
+    Spans::from(spans.flatten().collect::<Vec<_>>())
+}
+
+/// `?` 打开的帮助界面, 内容来自当前的按键表
+pub struct Help<'a> {
+    pub keys: &'a KeyMap,
+}
+
+impl<'a> Help<'a> {
+    fn group(&self, title: &'static str, bindings: impl Iterator<Item = Binding>) -> Vec<Spans<'static>> {
+        let mut lines = vec![Spans::from(Span::styled(title, crate::style::theme().info))];
+        for binding in bindings {
+            let chords = chords_text(self.keys, binding);
+            if !chords.is_empty() {
+                lines.push(Spans::from(format!("  {chords:<18} {}", binding.description())));
+            }
+        }
+        lines.push(Spans::default());
+        lines
+    }
+}
+
+impl<'a> Widget for Help<'a> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        Clear.render(area, buf);
+        let block = Block::default().borders(Borders::ALL).title("帮助 [任意键关闭]");
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
+            .split(inner);
+        let mut left = self.group("全局", Binding::ALL.into_iter().filter(|b| !b.is_live_room()));
+        left.push(Spans::from(Span::styled("输入行", crate::style::theme().info)));
+        left.extend(EDIT_KEYS.iter().map(|(_, chords, description)| Spans::from(format!("  {:<18} {description}", join_chords(chords.iter())))));
+        left.push(Spans::from("  房管确认时按 y 执行, 其它键取消"));
+        left.push(Spans::from("  命令面板中上面的操作名(如 open_room)都是命令"));
+        let right = self.group("直播间 / 回放 / 时间线", Binding::ALL.into_iter().filter(Binding::is_live_room));
+        Paragraph::new(left).render(columns[0], buf);
+        Paragraph::new(right).render(columns[1], buf);
+    }
+}
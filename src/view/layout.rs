@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use tui::{layout::{Layout, Constraint, Direction, Rect}, widgets::Widget, style::Style};
+
+const DEFAULT_WEIGHT: u16 = 2;
+const MAX_WEIGHT: u16 = 8;
+
+/// 同时显示多个页面的排布方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutMode {
+    /// 只显示当前页
+    Single,
+    /// 左右并排
+    Horizontal,
+    /// 上下排列
+    Vertical,
+    Grid,
+}
+
+impl LayoutMode {
+    pub fn next(self) -> Self {
+        match self {
+            LayoutMode::Single => LayoutMode::Horizontal,
+            LayoutMode::Horizontal => LayoutMode::Vertical,
+            LayoutMode::Vertical => LayoutMode::Grid,
+            LayoutMode::Grid => LayoutMode::Single,
+        }
+    }
+
+    /// 命令中使用的名字, 与 `layout.toml` 中相同
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "single" => Some(LayoutMode::Single),
+            "horizontal" => Some(LayoutMode::Horizontal),
+            "vertical" => Some(LayoutMode::Vertical),
+            "grid" => Some(LayoutMode::Grid),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LayoutMode::Single => "单页",
+            LayoutMode::Horizontal => "左右分屏",
+            LayoutMode::Vertical => "上下分屏",
+            LayoutMode::Grid => "网格",
+        }
+    }
+}
+
+/// 分屏设置, 分屏方式由 [`crate::service::layout`] 读写
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaneLayout {
+    pub mode: LayoutMode,
+    /// 各窗格的宽度(或高度)权重, 以页面标识(见 [`crate::page::Psh::key`])为键,
+    /// 标签页关闭或移动后也不会错位. 随会话保存在各标签页中
+    #[serde(skip)]
+    pub weights: BTreeMap<String, u16>,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self {
+            mode: LayoutMode::Single,
+            weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl PaneLayout {
+    pub fn cycle(&mut self) {
+        self.mode = self.mode.next();
+    }
+
+    fn weight(&self, key: &str) -> u16 {
+        self.weights.get(key).copied().unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    /// 调整窗格大小, `delta` 为正时变大
+    pub fn resize(&mut self, key: &str, delta: i16) {
+        let weight = self.weight(key) as i16 + delta;
+        self.weights.insert(key.to_string(), weight.clamp(1, MAX_WEIGHT as i16) as u16);
+    }
+
+    /// 页面关闭后不再记录它的权重
+    pub fn remove(&mut self, key: &str) {
+        self.weights.remove(key);
+    }
+
+    /// 每个显示出来的页面及其区域; 单页模式下只有当前页.
+    /// `keys` 为各标签页的标识, 按标签页顺序
+    pub fn panes(&self, area: Rect, keys: &[String], current: Option<usize>) -> Vec<(usize, Rect)> {
+        let pages = keys.len();
+        match (self.mode, current) {
+            (_, None) => Vec::new(),
+            (LayoutMode::Single, Some(current)) => vec![(current, area)],
+            _ if pages <= 1 => vec![(0, area)],
+            (LayoutMode::Horizontal, _) => self.split(area, Direction::Horizontal, keys, 0..pages),
+            (LayoutMode::Vertical, _) => self.split(area, Direction::Vertical, keys, 0..pages),
+            (LayoutMode::Grid, _) => {
+                let cols = (1..).find(|c| c * c >= pages).unwrap_or(1);
+                let rows = pages.div_ceil(cols);
+                let row_areas = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(vec![Constraint::Ratio(1, rows as u32); rows])
+                    .split(area);
+                row_areas.into_iter().enumerate().flat_map(|(row, row_area)|{
+                    let start = row * cols;
+                    self.split(row_area, Direction::Horizontal, keys, start..(start + cols).min(pages))
+                }).collect()
+            },
+        }
+    }
+
+    fn split(&self, area: Rect, direction: Direction, keys: &[String], panes: std::ops::Range<usize>) -> Vec<(usize, Rect)> {
+        let total: u32 = panes.clone().map(|p| self.weight(&keys[p]) as u32).sum();
+        let constraints: Vec<Constraint> = panes.clone().map(|p| Constraint::Ratio(self.weight(&keys[p]) as u32, total)).collect();
+        let areas = Layout::default().direction(direction).constraints(constraints).split(area);
+        panes.zip(areas).collect()
+    }
+}
+
+/// 给获得焦点的窗格顶边着色
+pub struct FocusMark(pub Style);
+
+impl Widget for FocusMark {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        buf.set_style(Rect { height: area.height.min(1), ..area }, self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("live:{i}")).collect()
+    }
+
+    const AREA: Rect = Rect { x: 0, y: 0, width: 120, height: 40 };
+
+    #[test]
+    fn single_shows_only_current_page() {
+        let layout = PaneLayout::default();
+        assert_eq!(layout.panes(AREA, &keys(3), Some(1)), vec![(1, AREA)]);
+        assert!(layout.panes(AREA, &keys(3), None).is_empty());
+    }
+
+    #[test]
+    fn split_by_weight() {
+        let mut layout = PaneLayout { mode: LayoutMode::Horizontal, ..Default::default() };
+        let panes = layout.panes(AREA, &keys(2), Some(0));
+        assert_eq!(panes.iter().map(|(_, r)| r.width).collect::<Vec<_>>(), [60, 60]);
+        layout.resize("live:0", 2);
+        let panes = layout.panes(AREA, &keys(2), Some(0));
+        assert_eq!(panes.iter().map(|(_, r)| r.width).collect::<Vec<_>>(), [80, 40]);
+        // 权重跟着页面走, 不跟着位置走
+        let swapped = vec!["live:1".to_string(), "live:0".to_string()];
+        let panes = layout.panes(AREA, &swapped, Some(0));
+        assert_eq!(panes.iter().map(|(_, r)| r.width).collect::<Vec<_>>(), [40, 80]);
+    }
+
+    #[test]
+    fn resize_is_clamped() {
+        let mut layout = PaneLayout::default();
+        layout.resize("a", -10);
+        assert_eq!(layout.weight("a"), 1);
+        layout.resize("a", 100);
+        assert_eq!(layout.weight("a"), MAX_WEIGHT);
+        layout.remove("a");
+        assert_eq!(layout.weight("a"), DEFAULT_WEIGHT);
+    }
+
+    #[test]
+    fn grid_fills_rows() {
+        let layout = PaneLayout { mode: LayoutMode::Grid, ..Default::default() };
+        let panes = layout.panes(AREA, &keys(3), Some(0));
+        assert_eq!(panes.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(panes.iter().map(|(_, r)| (r.y, r.height)).collect::<Vec<_>>(), [(0, 20), (0, 20), (20, 20)]);
+        assert_eq!(panes[2].1.width, 120);
+        assert_eq!(layout.panes(AREA, &keys(1), Some(0)), vec![(0, AREA)]);
+    }
+}
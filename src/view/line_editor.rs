@@ -0,0 +1,279 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::config::KeyChord;
+
+/// 编辑输入行时的按键, 优先于按键表, 不能在配置中修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKey {
+    Submit,
+    Cancel,
+    Left,
+    Right,
+    Home,
+    End,
+    Backspace,
+    Delete,
+    DeleteWord,
+    KillToStart,
+    HistoryPrev,
+    HistoryNext,
+    /// 只在命令面板中生效
+    Complete,
+}
+
+const fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+    KeyChord { code, modifiers }
+}
+
+const fn plain(code: KeyCode) -> KeyChord {
+    chord(code, KeyModifiers::NONE)
+}
+
+const fn ctrl(c: char) -> KeyChord {
+    chord(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+/// 编辑键的按键和说明, `handle_key` 和帮助界面都用这张表
+pub const EDIT_KEYS: [(EditKey, &[KeyChord], &str); 13] = [
+    (EditKey::Submit, &[plain(KeyCode::Enter)], "提交"),
+    (EditKey::Cancel, &[plain(KeyCode::Esc)], "取消"),
+    (EditKey::Left, &[plain(KeyCode::Left)], "光标左移"),
+    (EditKey::Right, &[plain(KeyCode::Right)], "光标右移"),
+    (EditKey::Home, &[plain(KeyCode::Home), ctrl('a')], "行首"),
+    (EditKey::End, &[plain(KeyCode::End), ctrl('e')], "行尾"),
+    (EditKey::Backspace, &[plain(KeyCode::Backspace)], "删除前一个字符"),
+    (EditKey::Delete, &[plain(KeyCode::Delete)], "删除后一个字符"),
+    (EditKey::DeleteWord, &[ctrl('w')], "删除前一个词"),
+    (EditKey::KillToStart, &[ctrl('u')], "删除到行首"),
+    (EditKey::HistoryPrev, &[plain(KeyCode::Up)], "上一条输入历史"),
+    (EditKey::HistoryNext, &[plain(KeyCode::Down)], "下一条输入历史"),
+    (EditKey::Complete, &[plain(KeyCode::Tab)], "补全命令"),
+];
+
+impl EditKey {
+    /// 按键对应的编辑键. 字符键以外的按键不区分修饰键, 例如 Shift-Left 也是左移
+    pub fn from_event(key: &KeyEvent) -> Option<Self> {
+        EDIT_KEYS.iter().find(|(_, chords, _)| chords.iter().any(|chord| {
+            chord.matches(key) || (chord.modifiers.is_empty() && !matches!(chord.code, KeyCode::Char(_)) && chord.code == key.code)
+        })).map(|(edit_key, _, _)| *edit_key)
+    }
+
+    fn entry(self) -> &'static (EditKey, &'static [KeyChord], &'static str) {
+        EDIT_KEYS.iter().find(|(edit_key, _, _)| *edit_key == self).expect("every edit key is in EDIT_KEYS")
+    }
+
+    pub fn chords(self) -> &'static [KeyChord] {
+        self.entry().1
+    }
+
+    pub fn description(self) -> &'static str {
+        self.entry().2
+    }
+}
+
+/// 单行文本编辑器, 光标以字符为单位, 显示时按宽度换算
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    text: String,
+    /// 光标前的字符数
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.chars().count();
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.text.char_indices().nth(cursor).map(|(i, _)| i).unwrap_or(self.text.len())
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let at = self.byte_index(self.cursor);
+        self.text.insert(at, c);
+        self.cursor += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        let at = self.byte_index(self.cursor);
+        self.text.insert_str(at, s);
+        self.cursor += s.chars().count();
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let at = self.byte_index(self.cursor);
+            self.text.remove(at);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let at = self.byte_index(self.cursor);
+            self.text.remove(at);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    /// Ctrl-w: 删除光标前的一个词(连同它前面的空白)
+    pub fn delete_word(&mut self) {
+        let chars: Vec<char> = self.text.chars().take(self.cursor).collect();
+        let mut start = chars.len();
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        let (from, to) = (self.byte_index(start), self.byte_index(self.cursor));
+        self.text.replace_range(from..to, "");
+        self.cursor = start;
+    }
+
+    /// Ctrl-u: 删除光标前的全部内容
+    pub fn kill_to_start(&mut self) {
+        let to = self.byte_index(self.cursor);
+        self.text.replace_range(..to, "");
+        self.cursor = 0;
+    }
+
+    /// 光标前内容的显示宽度
+    pub fn cursor_width(&self) -> usize {
+        self.text[..self.byte_index(self.cursor)].width()
+    }
+
+    /// 在 `width` 宽的区域内显示的文本片段, 以及光标在其中的列.
+    /// 文本过长时水平滚动, 保证光标可见
+    pub fn visible(&self, width: usize) -> (String, usize) {
+        let cursor = self.cursor_width();
+        if width == 0 {
+            return (String::new(), 0);
+        }
+        // 光标留在最后一列之内
+        let offset = cursor.saturating_sub(width - 1);
+        let mut skipped = 0;
+        let mut shown = 0;
+        let mut visible = String::new();
+        for c in self.text.chars() {
+            let w = c.width().unwrap_or(0);
+            if skipped < offset {
+                skipped += w;
+                continue;
+            }
+            if shown + w > width {
+                break;
+            }
+            shown += w;
+            visible.push(c);
+        }
+        (visible, cursor - skipped.min(cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> LineEditor {
+        let mut editor = LineEditor::default();
+        editor.set_text(text);
+        editor
+    }
+
+    #[test]
+    fn edits_at_cursor_by_char() {
+        let mut e = editor("弹幕");
+        e.left();
+        e.insert('a');
+        e.insert_str("测试");
+        assert_eq!(e.text(), "弹a测试幕");
+        e.backspace();
+        e.delete();
+        assert_eq!(e.text(), "弹a测");
+        e.home();
+        e.backspace();
+        e.left();
+        e.delete();
+        assert_eq!(e.text(), "a测");
+        e.end();
+        e.right();
+        e.insert('!');
+        assert_eq!(e.text(), "a测!");
+    }
+
+    #[test]
+    fn delete_word_and_kill() {
+        let mut e = editor("hello  big world");
+        e.delete_word();
+        assert_eq!(e.text(), "hello  big ");
+        e.delete_word();
+        assert_eq!(e.text(), "hello  ");
+        e.insert_str("x y");
+        e.left();
+        e.left();
+        e.kill_to_start();
+        assert_eq!(e.text(), " y");
+        e.delete_word();
+        assert_eq!(e.text(), " y");
+    }
+
+    #[test]
+    fn cursor_width_counts_wide_chars() {
+        let mut e = editor("ab你好");
+        assert_eq!(e.cursor_width(), 6);
+        e.left();
+        assert_eq!(e.cursor_width(), 4);
+    }
+
+    #[test]
+    fn edit_keys_from_table() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(EditKey::from_event(&key(KeyCode::Char('a'), KeyModifiers::CONTROL)), Some(EditKey::Home));
+        assert_eq!(EditKey::from_event(&key(KeyCode::Home, KeyModifiers::NONE)), Some(EditKey::Home));
+        assert_eq!(EditKey::from_event(&key(KeyCode::Left, KeyModifiers::SHIFT)), Some(EditKey::Left));
+        assert_eq!(EditKey::from_event(&key(KeyCode::Char('a'), KeyModifiers::NONE)), None);
+        assert_eq!(EditKey::from_event(&key(KeyCode::Char('w'), KeyModifiers::ALT)), None);
+        for (edit_key, chords, _) in EDIT_KEYS {
+            assert_eq!(edit_key.chords(), chords);
+        }
+    }
+
+    #[test]
+    fn visible_scrolls_to_keep_cursor() {
+        let e = editor("你好世界abc");
+        assert_eq!(e.visible(20), ("你好世界abc".into(), 11));
+        assert_eq!(e.visible(6), ("界abc".into(), 5));
+        assert_eq!(e.visible(0), (String::new(), 0));
+        let mut e = editor("你好世界");
+        e.home();
+        assert_eq!(e.visible(4), ("你好".into(), 0));
+        e.end();
+        assert_eq!(e.visible(4), ("界".into(), 2));
+    }
+}
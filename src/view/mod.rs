@@ -1,3 +1,70 @@
+pub mod line_editor;
+pub mod clipboard;
+pub mod layout;
+pub mod help;
+
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use tui::{text::{Span, Spans}, layout::Rect};
+use unicode_width::UnicodeWidthChar;
+
+/// 页面上次渲染时的可见区域. 渲染时只能拿到页面的共享引用, 所以用原子量记录
+#[derive(Debug, Default)]
+pub struct ViewSize {
+    width: AtomicU16,
+    height: AtomicU16,
+}
+
+impl ViewSize {
+    pub fn set(&self, area: Rect) {
+        self.width.store(area.width, Ordering::Relaxed);
+        self.height.store(area.height, Ordering::Relaxed);
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width.load(Ordering::Relaxed)
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height.load(Ordering::Relaxed)
+    }
+
+    /// 翻一页的距离, 保留一行上下文; 还没有渲染过时为 1
+    pub fn page(&self) -> usize {
+        (self.height() as usize).saturating_sub(1).max(1)
+    }
+}
+
+/// 按显示宽度把一行折成多行, 保留每一段的样式. 宽度为 0 时原样返回一行
+pub fn wrap_spans(spans: &Spans, width: u16) -> Vec<Spans<'static>> {
+    let width = width as usize;
+    let mut lines = Vec::new();
+    let mut line: Vec<Span<'static>> = Vec::new();
+    let mut line_width = 0;
+    for span in spans.0.iter() {
+        let mut content = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if width > 0 && line_width + w > width && line_width > 0 {
+                if !content.is_empty() {
+                    line.push(Span::styled(std::mem::take(&mut content), span.style));
+                }
+                lines.push(Spans::from(std::mem::take(&mut line)));
+                line_width = 0;
+            }
+            content.push(c);
+            line_width += w;
+        }
+        if !content.is_empty() {
+            line.push(Span::styled(content, span.style));
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(Spans::from(line));
+    }
+    lines
+}
+
 // use std::{sync::Arc, pin::Pin};
 
 // use tui::{widgets::{Widget, Block, List, Borders, Paragraph}, style::Style, text::{Spans, Span}, layout::{Layout, Rect}};
@@ -52,4 +119,47 @@
 //             },
 //         }
 //     }
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use tui::style::{Color, Style};
+
+    use super::*;
+
+    fn text(line: &Spans) -> String {
+        line.0.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn wrap_by_display_width() {
+        let lines = wrap_spans(&Spans::from("弹幕abcd"), 4);
+        assert_eq!(lines.iter().map(text).collect::<Vec<_>>(), ["弹幕", "abcd"]);
+    }
+
+    #[test]
+    fn wide_char_does_not_split() {
+        // 宽度为 3 时放不下第二个汉字, 整个挪到下一行
+        let lines = wrap_spans(&Spans::from("一二三"), 3);
+        assert_eq!(lines.iter().map(text).collect::<Vec<_>>(), ["一", "二", "三"]);
+    }
+
+    #[test]
+    fn styles_survive_wrapping() {
+        let red = Style::default().fg(Color::Red);
+        let spans = Spans::from(vec![Span::styled("ab", red), Span::from("cd")]);
+        let lines = wrap_spans(&spans, 3);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0[0].style, red);
+        assert_eq!(lines[0].0[1].content, "c");
+        assert_eq!(lines[0].0[1].style, Style::default());
+        assert_eq!(text(&lines[1]), "d");
+    }
+
+    #[test]
+    fn zero_width_keeps_one_line() {
+        let lines = wrap_spans(&Spans::from("不折行"), 0);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(text(&lines[0]), "不折行");
+        assert_eq!(wrap_spans(&Spans::default(), 10).len(), 1);
+    }
+}